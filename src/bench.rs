@@ -0,0 +1,121 @@
+use std::time::Instant;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::board::Board;
+use crate::map_generator::{generate, MapConfig};
+use crate::player::PlayerState;
+use crate::player_controller::{random_safe_action, BFSController, PlayerController};
+use crate::selfplay::{play_match, SelfPlayConfig};
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Thread counts to compare, each against a single-threaded search.
+    pub threads: Vec<usize>,
+    pub turn_time_milliseconds: u128,
+    /// Positions searched per thread count for the speed figures.
+    pub positions: usize,
+    /// Games per thread count for the strength figures, alternating seats.
+    pub games: usize,
+    pub seed: u64,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            threads: vec![1, 2, 4],
+            turn_time_milliseconds: 100,
+            positions: 8,
+            games: 8,
+            seed: 0,
+            height: 10,
+            width: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchRow {
+    pub threads: usize,
+    /// Threads the searches actually ran on, the fewest over all positions.
+    pub effective_threads: usize,
+    pub nodes: u64,
+    pub seconds: f64,
+    pub nodes_per_second: f64,
+    /// Win rate against one thread with the same turn time, draws counting half.
+    pub score: f64,
+}
+
+/// Measures `BFSController` search speed and strength for each of `config.threads`.
+#[allow(clippy::result_unit_err)]
+pub fn run(config: &BenchConfig) -> Result<Vec<BenchRow>, ()> {
+    let positions = positions(config)?;
+    let mut rows = Vec::new();
+    for &threads in &config.threads {
+        let mut nodes = 0;
+        let mut seconds = 0.0;
+        let mut effective_threads = usize::MAX;
+        for (board, player_id) in &positions {
+            let mut controller = BFSController::new(config.turn_time_milliseconds);
+            controller.threads = threads;
+            let start = Instant::now();
+            controller.get_action(board, *player_id);
+            seconds += start.elapsed().as_secs_f64();
+            nodes += controller.last_nodes;
+            effective_threads = effective_threads.min(controller.last_threads);
+        }
+        rows.push(BenchRow {
+            threads,
+            effective_threads: effective_threads.min(threads),
+            nodes,
+            seconds,
+            nodes_per_second: nodes as f64 / seconds.max(f64::EPSILON),
+            score: strength(config, threads),
+        });
+    }
+    Ok(rows)
+}
+
+/// Midgame positions: generated arenas after a few random safe moves each.
+fn positions(config: &BenchConfig) -> Result<Vec<(Board, usize)>, ()> {
+    let mut positions = Vec::new();
+    for index in 0..config.positions {
+        let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(index as u64));
+        let map = MapConfig { height: config.height, width: config.width, ..MapConfig::default() };
+        let mut board = generate(&map, &mut rng)?;
+        for turn in 0..6 {
+            let player_id = turn % 2;
+            let action = random_safe_action(&board, player_id, &mut rng);
+            match board.apply_action(player_id, action) {
+                Ok(next) if next.players()[player_id].get_state() != PlayerState::Dead => { board = next }
+                _ => { break }
+            }
+        }
+        positions.push((board, index % 2));
+    }
+    Ok(positions)
+}
+
+fn strength(config: &BenchConfig, threads: usize) -> f64 {
+    let mut points = 0.0;
+    for game in 0..config.games {
+        let seat = game % 2;
+        let single = format!("bfs:{}", config.turn_time_milliseconds);
+        let mut controllers = vec![single.clone(), single];
+        controllers[seat] = format!("bfs@{}:{}", threads, config.turn_time_milliseconds);
+        let match_config = SelfPlayConfig {
+            controllers,
+            height: config.height,
+            width: config.width,
+            ..Default::default()
+        };
+        let seed = config.seed ^ game as u64;
+        match play_match(&match_config, &mut StdRng::seed_from_u64(seed)) {
+            Ok(Some(winner_id)) if winner_id == seat => { points += 1.0 }
+            Ok(None) => { points += 0.5 }
+            _ => {}
+        }
+    }
+    points / config.games.max(1) as f64
+}
@@ -0,0 +1,97 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Which cells within `Blast::radius` of a bomb it reaches.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum BlastShape {
+    /// Every cell within the radius in any direction. On hex grids, every cell within the
+    /// radius, as for `Diamond`.
+    #[default]
+    Square,
+    /// Only the straight lines out from the bomb, along the grid's moves.
+    Plus,
+    /// Cells at most the radius away in moves, without diagonals on square grids.
+    Diamond,
+}
+
+impl FromStr for BlastShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => { Ok(BlastShape::Square) }
+            "plus" => { Ok(BlastShape::Plus) }
+            "diamond" => { Ok(BlastShape::Diamond) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for BlastShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlastShape::Square => { write!(f, "square") }
+            BlastShape::Plus => { write!(f, "plus") }
+            BlastShape::Diamond => { write!(f, "diamond") }
+        }
+    }
+}
+
+/// What a bomb does when it goes off. The default clears the eight cells around it, as bombs
+/// always have.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Blast {
+    pub shape: BlastShape,
+    pub radius: usize,
+    /// Players caught in the blast take damage, losing armor or their life.
+    pub damage: bool,
+    /// Bombs lying in the blast go off too, with the same shape and radius.
+    pub chain: bool,
+}
+
+impl Default for Blast {
+    fn default() -> Self {
+        Blast {
+            shape: BlastShape::Square,
+            radius: 1,
+            damage: false,
+            chain: false,
+        }
+    }
+}
+
+/// Parses `<shape>:<radius>`, optionally followed by `:damage` and `:chain`, e.g.
+/// `plus:3:damage:chain`.
+impl FromStr for Blast {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mut blast = Blast {
+            shape: parts.next().ok_or(())?.parse()?,
+            radius: parts.next().ok_or(())?.parse().map_err(|_| ())?,
+            ..Blast::default()
+        };
+        for part in parts {
+            match part {
+                "damage" => { blast.damage = true }
+                "chain" => { blast.chain = true }
+                _ => { return Err(()) }
+            }
+        }
+        Ok(blast)
+    }
+}
+
+impl Display for Blast {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.shape, self.radius)?;
+        if self.damage {
+            write!(f, ":damage")?;
+        }
+        if self.chain {
+            write!(f, ":chain")?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,20 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
-use crate::game::GameState;
-use crate::player::{Action, Player, PlayerState};
+use std::hash::{Hash, Hasher};
+use tracing::trace;
+use crate::event::{DeathCause, GameEvent};
+use crate::player::{Action, Player, PlayerState, RelativeAction, Steering, ACTIONS_WITH_USE, HEX_ACTIONS_WITH_USE, RELATIVE_ACTIONS};
 use crate::position::Position;
-use crate::power_up::PowerUp;
+use crate::blast::Blast;
+use crate::rules::{Collision, HeadOn, Rules, TrailPersistence};
+use crate::power_up::{PowerUp, MAX_INVENTORY_SLOTS};
+use crate::grid::Grid;
+use crate::sudden_death::{ring, SuddenDeath};
+use crate::topology::Topology;
+use crate::turn_limit::TurnLimit;
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum CellState {
     Empty,
     PowerUp {
@@ -19,22 +29,31 @@ pub enum CellState {
     },
 }
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct BoardCell {
     position: Position,
     state: CellState,
+    /// The turn this trail cell fades back to empty, under `TrailPersistence::Fading`.
+    expires: Option<usize>,
 }
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Board {
     width: usize,
     height: usize,
     cells: Vec<Vec<BoardCell>>,
-    players: Vec<Player>
+    players: Vec<Player>,
+    grid: Grid,
+    rules: Rules,
+    /// Each player's trail, oldest cell first, for `PowerUp::Eraser`. Cells cleared since are
+    /// skipped when erasing.
+    trails: Vec<VecDeque<Position>>,
+    /// Turns completed so far, advanced by `end_turn`.
+    turn: usize,
 }
 
 impl BoardCell {
-    fn get_position(&self) -> Position {
+    pub fn get_position(&self) -> Position {
         self.position
     }
 
@@ -42,50 +61,70 @@ impl BoardCell {
         self.state
     }
 
+    /// The turn this cell, a fading trail, turns empty again; `None` for any other cell.
+    pub fn expires(&self) -> Option<usize> {
+        self.expires
+    }
+
     fn set_state(&mut self, state: CellState) {
         self.state = state;
+        self.expires = None;
     }
 }
 
+#[allow(clippy::result_unit_err)]
 impl Board {
     pub fn new_default(height: usize, width: usize) -> Result<Self, ()> {
+        if height == 0 || width == 0 {
+            return Err(())
+        }
+        Board::with_spawns(height, width, &[Position::new(0, 0), Position::new(height - 1, width - 1)])
+    }
+
+    /// An empty `height` x `width` board with one player per spawn, numbered in order.
+    pub fn with_spawns(height: usize, width: usize, spawns: &[Position]) -> Result<Self, ()> {
         if height == 0 || width == 0 {
             return Err(())
         }
         let mut cells = Vec::new();
         for i_row in 0..height {
             let mut row = Vec::new();
-            for i_col in 0..height {
+            for i_col in 0..width {
                 let position = Position {
                     row: i_row,
                     column: i_col
                 };
-                let state = if i_row == 0 && i_col == 0 {
-                    CellState::Occupied { player_id: 0 }
-                } else if i_row == (height - 1) && i_col == (width - 1) {
-                    CellState::Occupied { player_id: 1 }
-                } else {
-                    CellState::Empty
+                let state = match spawns.iter().position(|spawn| *spawn == position) {
+                    Some(player_id) => { CellState::Occupied { player_id } }
+                    None => { CellState::Empty }
                 };
                 let cell = BoardCell {
                     position,
-                    state
+                    state,
+                    expires: None
                 };
                 row.push(cell);
             }
             cells.push(row);
         }
 
-        let players = vec![
-            Player::new(0, Position::new(0, 0)),
-            Player::new(1, Position::new(height - 1, width - 1))
-        ];
+        let mut players = Vec::new();
+        for (player_id, spawn) in spawns.iter().enumerate() {
+            if spawn.row >= height || spawn.column >= width || spawns[..player_id].contains(spawn) {
+                return Err(())
+            }
+            players.push(Player::new(player_id, *spawn));
+        }
 
         Ok(Board {
             width,
             height,
             cells,
-            players
+            players,
+            grid: Grid::Square,
+            rules: Rules::default(),
+            trails: vec![VecDeque::new(); spawns.len()],
+            turn: 0,
         })
     }
 
@@ -97,10 +136,233 @@ impl Board {
         self.width
     }
 
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Switches to `rules`, with at most `MAX_INVENTORY_SLOTS` inventory slots, or returns `Err`
+    /// if their topology does not fit this board's grid, see `Grid::fits`.
+    pub fn set_rules(&mut self, rules: Rules) -> Result<(), ()> {
+        if !self.grid.fits(rules.topology, self.height) {
+            return Err(())
+        }
+        self.rules = rules;
+        self.rules.inventory_slots = rules.inventory_slots.min(MAX_INVENTORY_SLOTS);
+        Ok(())
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.rules.topology
+    }
+
+    /// Switches the topology, or returns `Err` if it does not fit the grid, see `Grid::fits`.
+    pub fn set_topology(&mut self, topology: Topology) -> Result<(), ()> {
+        if !self.grid.fits(topology, self.height) {
+            return Err(())
+        }
+        self.rules.topology = topology;
+        Ok(())
+    }
+
+    /// Offsets `position`, wrapping around the edges this board's topology joins.
+    pub fn offset(&self, position: Position, row_offset: isize, col_offset: isize) -> Result<Position, ()> {
+        self.rules.topology.offset(position, row_offset, col_offset, self.height, self.width)
+    }
+
+    pub fn grid(&self) -> Grid {
+        self.grid
+    }
+
+    /// Switches the cell shape, or returns `Err` if it does not fit the topology: hex boards that
+    /// wrap rows need an even height.
+    pub fn set_grid(&mut self, grid: Grid) -> Result<(), ()> {
+        if !grid.fits(self.rules.topology, self.height) {
+            return Err(())
+        }
+        self.grid = grid;
+        Ok(())
+    }
+
+    pub fn steering(&self) -> Steering {
+        self.rules.steering
+    }
+
+    pub fn set_steering(&mut self, steering: Steering) {
+        self.rules.steering = steering;
+    }
+
+    /// How many power-ups each player can hold for later, or 0 if power-ups take effect as
+    /// soon as they are collected.
+    pub fn inventory_slots(&self) -> usize {
+        self.rules.inventory_slots
+    }
+
+    /// Lets players hold up to `slots` power-ups, at most `MAX_INVENTORY_SLOTS`, and use them
+    /// with `Action::Use`. A power-up collected with every slot taken takes effect at once.
+    pub fn set_inventory_slots(&mut self, slots: usize) {
+        self.rules.inventory_slots = slots.min(MAX_INVENTORY_SLOTS);
+    }
+
+    pub fn blast(&self) -> Blast {
+        self.rules.blast
+    }
+
+    /// Sets what bombs do when they go off.
+    pub fn set_blast(&mut self, blast: Blast) {
+        self.rules.blast = blast;
+    }
+
+    pub fn sudden_death(&self) -> Option<SuddenDeath> {
+        self.rules.sudden_death
+    }
+
+    pub fn set_sudden_death(&mut self, sudden_death: Option<SuddenDeath>) {
+        self.rules.sudden_death = sudden_death;
+    }
+
+    pub fn turn_limit(&self) -> Option<TurnLimit> {
+        self.rules.turn_limit
+    }
+
+    pub fn set_turn_limit(&mut self, turn_limit: Option<TurnLimit>) {
+        self.rules.turn_limit = turn_limit;
+    }
+
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    /// The turn `position` turns into a wall under sudden death, if it ever does.
+    pub fn closing_turn(&self, position: Position) -> Option<usize> {
+        let sudden_death = self.rules.sudden_death?;
+        let ring = ring(position, self.height, self.width);
+        // The innermost ring never closes, so the arena can't vanish entirely.
+        if ring >= (self.height.min(self.width) - 1) / 2 {
+            return None
+        }
+        Some(sudden_death.closing_turn(ring))
+    }
+
+    /// Advances the turn counter, empties fading trails that have run out and closes any rings
+    /// sudden death schedules for the new turn.
+    /// Cells in a closed ring become walls; players standing in one as it closes take damage and
+    /// keep their cell until they leave it if their armor saves them. The player whose turn it
+    /// was loses a turn of phase.
+    pub fn end_turn(&mut self) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        let active_id = self.turn % self.players.len().max(1);
+        if let Some(player) = self.players.get_mut(active_id) {
+            player.wear_off_phase();
+        }
+        self.turn += 1;
+        let mut faded = Vec::new();
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let position = Position::new(row, column);
+                if let CellState::Owned { player_id } = self.cells[row][column].get_state() {
+                    if self.cells[row][column].expires().is_some_and(|turn| turn <= self.turn) {
+                        self.cells[row][column].set_state(CellState::Empty);
+                        self.trails[player_id].retain(|trail| *trail != position);
+                        faded.push(position);
+                    }
+                }
+                let closing_turn = match self.closing_turn(position) {
+                    Some(closing_turn) if closing_turn <= self.turn => { closing_turn }
+                    _ => { continue }
+                };
+                match self.cells[row][column].get_state() {
+                    CellState::Wall => {}
+                    // Armor only holds off the ring as it closes; the cell walls up once left.
+                    CellState::Occupied { .. } if closing_turn < self.turn => {}
+                    CellState::Occupied { player_id } => {
+                        let mut player = self.players[player_id];
+                        let _ = player.take_damage();
+                        self.players[player_id] = player;
+                        match player.get_state() {
+                            PlayerState::Alive { armor, .. } => {
+                                events.push(GameEvent::ArmorAbsorbedHit { player_id, position, armor_left: armor });
+                            }
+                            PlayerState::Dead => {
+                                self.cells[row][column].set_state(CellState::Wall);
+                                events.push(GameEvent::PlayerDied { player_id, cause: DeathCause::SuddenDeath });
+                            }
+                        }
+                    }
+                    _ => { self.cells[row][column].set_state(CellState::Wall) }
+                }
+            }
+        }
+        if !faded.is_empty() {
+            events.insert(0, GameEvent::TrailFaded { cleared_cells: faded });
+        }
+        events
+    }
+
+    /// Uses up a turn `player_id` has to skip after being frozen, returning whether there was
+    /// one.
+    pub fn skip_frozen_turn(&mut self, player_id: usize) -> bool {
+        match self.players.get_mut(player_id) {
+            Some(player) => { player.thaw() }
+            None => { false }
+        }
+    }
+
+    /// Every action on this board: the grid's moves, followed by `Action::Use` when players
+    /// hold power-ups. Policies over actions, such as a `Network`'s, follow this order.
+    pub fn actions(&self) -> &'static [Action] {
+        match (self.grid, self.rules.inventory_slots) {
+            (_, 0) => { self.grid.actions() }
+            (Grid::Square, _) => { &ACTIONS_WITH_USE }
+            (Grid::Hex, _) => { &HEX_ACTIONS_WITH_USE }
+        }
+    }
+
+    /// The grid's moves, in clockwise order.
+    pub fn moves(&self) -> &'static [Action] {
+        self.grid.actions()
+    }
+
+    /// Where `action` leads from `position`.
+    pub fn step(&self, position: Position, action: Action) -> Result<Position, ()> {
+        let (row_offset, col_offset) = self.grid.step(action, position)?;
+        self.offset(position, row_offset, col_offset)
+    }
+
+    /// The cells one move away from `position`, in `actions` order.
+    pub fn neighbors(&self, position: Position) -> Vec<Position> {
+        self.moves().iter().filter_map(|action| self.step(position, *action).ok()).collect()
+    }
+
     pub fn players(&self) -> &Vec<Player> {
         &self.players
     }
 
+    /// Each player's team, by player id. Every player starts on a team of their own.
+    pub fn teams(&self) -> Vec<usize> {
+        self.players.iter().map(|player| player.team()).collect()
+    }
+
+    /// Puts each player on the team given for them, in player order. Teams are numbered from 0
+    /// and there can be no more of them than players.
+    pub fn set_teams(&mut self, teams: &[usize]) -> Result<(), ()> {
+        if teams.len() != self.players.len() || teams.iter().any(|team| *team >= teams.len()) {
+            return Err(())
+        }
+        for (player, team) in self.players.iter_mut().zip(teams) {
+            player.set_team(*team);
+        }
+        Ok(())
+    }
+
+    pub fn team(&self, player_id: usize) -> usize {
+        self.players[player_id].team()
+    }
+
+    /// Whether two players are on the same team, which a player is with themselves.
+    pub fn allies(&self, player_id: usize, other_id: usize) -> bool {
+        self.team(player_id) == self.team(other_id)
+    }
+
     pub fn get_cell(&self, position: Position) -> Result<&BoardCell, ()> {
         match self.cells.get(position.row) {
             None => { Err(()) }
@@ -113,6 +375,30 @@ impl Board {
         }
     }
 
+    /// Overwrites a cell as a `PlayerView` remembers it.
+    pub(crate) fn set_cell(&mut self, position: Position, state: CellState, expires: Option<usize>) -> Result<(), ()> {
+        self.update_cell_state(position, state)?;
+        self.cells[position.row][position.column].expires = expires;
+        Ok(())
+    }
+
+    /// Replaces a player with how a `PlayerView` remembers them, leaving the cells as they are.
+    pub(crate) fn set_player(&mut self, player: Player) -> Result<(), ()> {
+        *self.players.get_mut(player.get_id()).ok_or(())? = player;
+        Ok(())
+    }
+
+    /// Drops the cells of `player_id`'s trail that no longer show as theirs, so a `PlayerView`
+    /// only erases the trail it knows about.
+    pub(crate) fn forget_trail(&mut self, player_id: usize) {
+        let mut trail = match self.trails.get_mut(player_id) {
+            Some(trail) => { std::mem::take(trail) }
+            None => { return }
+        };
+        trail.retain(|position| self.get_cell(*position).map(|cell| cell.get_state()) == Ok(CellState::Owned { player_id }));
+        self.trails[player_id] = trail;
+    }
+
     fn update_cell_state(&mut self, position: Position, state: CellState) -> Result<(), ()> {
         match self.get_cell(position) {
             Ok(_) => { self.cells[position.row][position.column].set_state(state) }
@@ -121,35 +407,133 @@ impl Board {
         Ok(())
     }
 
+    pub fn place_power_up(&mut self, position: Position, power_up: PowerUp) -> Result<(), ()> {
+        match self.get_cell(position)?.get_state() {
+            CellState::Empty => { self.update_cell_state(position, CellState::PowerUp { power_up }) }
+            _ => { Err(()) }
+        }
+    }
+
+    pub fn place_wall(&mut self, position: Position) -> Result<(), ()> {
+        match self.get_cell(position)?.get_state() {
+            CellState::Empty => { self.update_cell_state(position, CellState::Wall) }
+            _ => { Err(()) }
+        }
+    }
+
+    /// Kills `player_id` where they stand, leaving their head as part of their trail.
+    pub fn eliminate_player(&self, player_id: usize) -> Result<Board, ()> {
+        let mut new_board = self.clone();
+        let position = match self.players.get(player_id).ok_or(())?.get_state() {
+            PlayerState::Alive { position, .. } => { position }
+            PlayerState::Dead => { return Err(()) }
+        };
+        new_board.leave_trail(player_id, position)?;
+        new_board.players[player_id].set_state(PlayerState::Dead);
+        Ok(new_board)
+    }
+
     pub fn apply_action(&self, player_id: usize, action: Action) -> Result<Board, ()> {
-        let player_position = match self.players[player_id].get_state() {
-            PlayerState::Alive { position, boost: _, armor: _ } => {
-                position
+        self.apply_action_recording(player_id, action, None)
+    }
+
+    /// Like `apply_action`, also appending what the move set off to `events`.
+    pub fn apply_action_with_events(&self, player_id: usize, action: Action,
+                                    events: &mut Vec<GameEvent>) -> Result<Board, ()> {
+        self.apply_action_recording(player_id, action, Some(events))
+    }
+
+    fn apply_action_recording(&self, player_id: usize, action: Action,
+                              mut events: Option<&mut Vec<GameEvent>>) -> Result<Board, ()> {
+        let (player_position, heading) = match self.players[player_id].get_state() {
+            PlayerState::Alive { position, heading, .. } => {
+                (position, heading)
             }
             PlayerState::Dead => { return Err(()) }
         };
-        let position_result = action.offset_position(&player_position);
+        if !self.legal_actions(player_id).contains(&action) {
+            return Err(())
+        }
+        if action == Action::Use {
+            let heading = heading.ok_or(())?;
+            let mut board = self.clone();
+            let mut player = board.players[player_id];
+            let mut inventory = player.inventory();
+            let power_up = inventory.take_oldest().ok_or(())?;
+            player.set_inventory(inventory);
+            board.activate(&mut player, power_up, player_position, &mut events);
+            board.players[player_id] = player;
+            // A teleport moves the head, so the step ahead is taken from where it landed.
+            let from = match player.get_state() {
+                PlayerState::Alive { position, .. } => { position }
+                // Caught in a chain of their own bombs.
+                PlayerState::Dead => { return Ok(board) }
+            };
+            let new_position = heading.offset_position(&from, &board)?;
+            trace!(player_id, ?action, %from, to = %new_position, "apply action");
+            record(&mut events, GameEvent::Moved { player_id, action, from, to: new_position });
+            return board.move_player_recording(player_id, new_position, events)
+        }
+        let new_position = action.offset_position(&player_position, self)?;
+        trace!(player_id, ?action, from = %player_position, to = %new_position, "apply action");
+        record(&mut events, GameEvent::Moved { player_id, action, from: player_position, to: new_position });
+
+        let mut new_board = self.move_player_recording(player_id, new_position, events)?;
+        let _ = new_board.players[player_id].set_heading(action);
+        Ok(new_board)
+    }
 
-        let new_position = match position_result {
-            Ok(position) => {
-                position
+    /// The moves `player_id` may choose from under this board's steering: every grid move, or
+    /// only left, straight and right of their heading, plus `Action::Use` while they hold a
+    /// power-up and have a heading. Moves may still leave the board.
+    pub fn legal_actions(&self, player_id: usize) -> Vec<Action> {
+        let (heading, inventory) = match self.players.get(player_id) {
+            Some(player) => {
+                match player.get_state() {
+                    PlayerState::Alive { heading, .. } => { (heading, player.inventory()) }
+                    PlayerState::Dead => { return Vec::new() }
+                }
+            }
+            None => { return Vec::new() }
+        };
+        let mut legal = match (self.rules.steering, heading) {
+            (Steering::Relative, Some(heading)) => {
+                RELATIVE_ACTIONS.iter()
+                    .filter_map(|relative| relative.resolve(heading, self.moves()).ok())
+                    .collect()
             }
-            Err(err) => { return Err(err) }
+            _ => { self.moves().to_vec() }
         };
+        if heading.is_some() && !inventory.is_empty() {
+            legal.push(Action::Use);
+        }
+        legal
+    }
 
-        self.move_player(player_id, new_position)
+    /// The absolute move `relative` makes for `player_id`. Before their first move a player
+    /// has no heading, so this fails and any grid move is legal.
+    pub fn relative_action(&self, player_id: usize, relative: RelativeAction) -> Result<Action, ()> {
+        match self.players.get(player_id).ok_or(())?.get_state() {
+            PlayerState::Alive { heading: Some(heading), .. } => { relative.resolve(heading, self.moves()) }
+            _ => { Err(()) }
+        }
     }
 
     pub fn move_player(&self, player_id: usize, destination: Position) -> Result<Self, ()> {
+        self.move_player_recording(player_id, destination, None)
+    }
+
+    fn move_player_recording(&self, player_id: usize, destination: Position,
+                             mut events: Option<&mut Vec<GameEvent>>) -> Result<Self, ()> {
         let mut new_board = self.clone();
         let mut player = new_board.players[player_id];
         match player.get_state() {
-            PlayerState::Alive { position, boost, armor } => {
-                match new_board.update_cell_state(position, CellState::Owned { player_id }) {
+            PlayerState::Alive { position, boost, armor, heading, phase, frozen } => {
+                match new_board.leave_trail(player_id, position) {
                     Ok(_) => {}
                     Err(_) => { return Err(()) }
                 }
-                player.set_state(PlayerState::Alive { position: destination, boost, armor });
+                player.set_state(PlayerState::Alive { position: destination, boost, armor, heading, phase, frozen });
             }
             PlayerState::Dead => { return Err(()) }
         }
@@ -161,32 +545,44 @@ impl Board {
         match to_cell.get_state() {
             CellState::Empty => {}
             CellState::PowerUp { power_up } => {
-                match power_up {
-                    PowerUp::DoubleSpeed { duration } => {
-                        player.speed_boost(duration).unwrap()
-                    }
-                    PowerUp::Armor => {
-                        player.armor_up().unwrap()
-                    }
-                    PowerUp::Bomb => {
-                        new_board.explode_around(destination)
-                    }
-                };
+                trace!(player_id, ?power_up, %destination, "power-up picked up");
+                record(&mut events, GameEvent::PowerUpCollected { player_id, power_up, position: destination });
+                let mut inventory = player.inventory();
+                if inventory.add(power_up, self.rules.inventory_slots).is_ok() {
+                    player.set_inventory(inventory);
+                } else {
+                    new_board.activate(&mut player, power_up, destination, &mut events);
+                }
             }
             CellState::Wall => {
-                player.take_damage().unwrap();
+                crash(&mut player, self.rules.wall);
+                record(&mut events, hit_event(player_id, destination, &player, DeathCause::Wall));
             }
-            CellState::Owned { .. } => {
-                player.take_damage().unwrap();
+            CellState::Owned { player_id: owner_id } => {
+                if let PlayerState::Alive { phase: 1.., .. } = player.get_state() {
+                    trace!(player_id, %destination, "phased through a trail");
+                } else if owner_id != player_id && !self.rules.friendly_fire && self.allies(player_id, owner_id) {
+                    trace!(player_id, %destination, "passed through a teammate's trail");
+                } else {
+                    crash(&mut player, self.rules.trail);
+                    record(&mut events, hit_event(player_id, destination, &player, DeathCause::Trail { owner_id }));
+                }
             }
-            CellState::Occupied { .. } => {
-                player.set_state(PlayerState::Dead)
+            CellState::Occupied { player_id: other_id } => {
+                player.set_state(PlayerState::Dead);
+                record(&mut events, GameEvent::PlayerDied { player_id, cause: DeathCause::Collision { other_id } });
+                if self.rules.head_on == HeadOn::Both {
+                    new_board.players[other_id].set_state(PlayerState::Dead);
+                    let _ = new_board.leave_trail(other_id, destination);
+                    record(&mut events, GameEvent::PlayerDied { player_id: other_id, cause: DeathCause::Collision { other_id: player_id } });
+                }
             }
         }
 
+        // A teleport may have taken the player somewhere other than `destination`.
         match player.get_state() {
-            PlayerState::Alive { .. } => {
-                match new_board.update_cell_state(destination, CellState::Occupied { player_id }) {
+            PlayerState::Alive { position, .. } => {
+                match new_board.update_cell_state(position, CellState::Occupied { player_id }) {
                     Ok(_) => {}
                     Err(_) => { return Err(()) }
                 }
@@ -196,68 +592,233 @@ impl Board {
 
         new_board.players[player_id] = player;
 
-        //println!("{}", new_board);
+        if player.get_state() == PlayerState::Dead {
+            trace!(player_id, %destination, "player died");
+        }
+        trace!(player_id, board = %new_board, "moved");
 
         Ok(new_board)
     }
 
-    fn explode_around(&mut self, position: Position) {
-        let positions = [
-            position.offset(-1, -1),
-            position.offset(-1, 0),
-            position.offset(-1, 1),
-            position.offset(0, -1),
-            //position.offset(0, 0),
-            position.offset(0, 1),
-            position.offset(1, -1),
-            position.offset(1, 0),
-            position.offset(1, 1),
-        ];
+    /// Puts `power_up` into effect for `player`, who stands at `position`.
+    fn activate(&mut self, player: &mut Player, power_up: PowerUp, position: Position,
+                events: &mut Option<&mut Vec<GameEvent>>) {
+        let player_id = player.get_id();
+        record(events, GameEvent::PowerUpActivated { player_id, power_up, position });
+        match power_up {
+            PowerUp::DoubleSpeed { duration } => {
+                let _ = player.speed_boost(duration);
+            }
+            PowerUp::Armor => {
+                let _ = player.armor_up(self.rules.max_armor);
+            }
+            PowerUp::Bomb => {
+                self.detonate(player, position, events);
+            }
+            PowerUp::Phase { duration } => {
+                let _ = player.phase_out(duration);
+            }
+            PowerUp::Eraser { length } => {
+                let cleared_cells = self.erase_trail(player_id, length);
+                record(events, GameEvent::TrailErased { player_id, cleared_cells });
+            }
+            PowerUp::Freeze => {
+                let teams = self.teams();
+                for other in self.players.iter_mut().filter(|other| teams[other.get_id()] != teams[player_id]) {
+                    let _ = other.freeze();
+                }
+            }
+            PowerUp::Teleport => {
+                if let Some(target) = self.teleport_target(player_id, position) {
+                    let _ = player.relocate(target);
+                    let _ = self.leave_trail(player_id, position);
+                    let _ = self.update_cell_state(target, CellState::Occupied { player_id });
+                    record(events, GameEvent::Teleported { player_id, from: position, to: target });
+                }
+            }
+        }
+    }
+
+    /// Turns `position`, just left by `player_id`, into their trail, or empties it if the rules
+    /// leave no trails.
+    fn leave_trail(&mut self, player_id: usize, position: Position) -> Result<(), ()> {
+        match self.rules.trails {
+            TrailPersistence::Permanent => {
+                self.update_cell_state(position, CellState::Owned { player_id })?;
+                self.trails[player_id].push_back(position);
+                Ok(())
+            }
+            TrailPersistence::Off => { self.update_cell_state(position, CellState::Empty) }
+            TrailPersistence::Fading { turns } => {
+                self.update_cell_state(position, CellState::Owned { player_id })?;
+                self.cells[position.row][position.column].expires = Some(self.turn + turns);
+                self.trails[player_id].push_back(position);
+                Ok(())
+            }
+        }
+    }
+
+    /// Clears up to `length` of the oldest cells still in `player_id`'s trail.
+    fn erase_trail(&mut self, player_id: usize, length: usize) -> Vec<Position> {
+        let mut cleared = Vec::new();
+        while cleared.len() < length {
+            let position = match self.trails[player_id].pop_front() {
+                Some(position) => { position }
+                None => { break }
+            };
+            // Bombs and other players may have taken the cell since.
+            if self.get_cell(position).map(|cell| cell.get_state()) == Ok(CellState::Owned { player_id }) {
+                let _ = self.update_cell_state(position, CellState::Empty);
+                cleared.push(position);
+            }
+        }
+        cleared
+    }
+
+    /// Where a teleport from `position` lands: an empty cell with an empty neighbour, so the
+    /// player isn't boxed in on arrival. The pick is spread over the candidates by hashing the
+    /// turn, player and position, so searches see the same landing the game will.
+    fn teleport_target(&self, player_id: usize, position: Position) -> Option<Position> {
+        let is_empty = |position: Position| self.get_cell(position).map(|cell| cell.get_state()) == Ok(CellState::Empty);
+        let mut candidates = Vec::new();
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let candidate = Position::new(row, column);
+                if is_empty(candidate) && self.neighbors(candidate).into_iter().any(is_empty) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return None
+        }
+        let mut hasher = DefaultHasher::new();
+        (self.turn, player_id, position.row, position.column).hash(&mut hasher);
+        Some(candidates[hasher.finish() as usize % candidates.len()])
+    }
 
-        for position in positions {
-            match position {
-                Ok(position) => {
-                    let _ = self.explode_cell(position);
+    /// Sets off a bomb of `player`'s at `position`, then any bombs its blast reaches if the
+    /// blast chains, each clearing the cells it reaches and damaging players if the blast does.
+    fn detonate(&mut self, player: &mut Player, position: Position, events: &mut Option<&mut Vec<GameEvent>>) {
+        let player_id = player.get_id();
+        let mut detonated = vec![position];
+        let mut pending = VecDeque::from([position]);
+        while let Some(position) = pending.pop_front() {
+            // On a wrapped board several offsets can land on one cell, which is only hit once.
+            let mut targets = Vec::new();
+            for (row_offset, col_offset) in self.grid.blast_offsets(position, self.rules.blast.shape, self.rules.blast.radius) {
+                match self.offset(position, row_offset, col_offset) {
+                    Ok(target) if target != position && !targets.contains(&target) => { targets.push(target) }
+                    _ => {}
                 }
-                Err(_) => {}
             }
+            let mut cleared_cells = Vec::new();
+            for target in targets {
+                // `player` isn't back on the board yet, so look for them by position.
+                if let PlayerState::Alive { position: player_position, .. } = player.get_state() {
+                    if player_position == target {
+                        if self.rules.blast.damage {
+                            self.blast_hit(player, target, player_id, events);
+                        }
+                        continue
+                    }
+                }
+                match self.get_cell(target).map(|cell| cell.get_state()) {
+                    Ok(CellState::Occupied { player_id: other_id }) if self.rules.blast.damage => {
+                        if !self.rules.friendly_fire && self.allies(player_id, other_id) {
+                            continue
+                        }
+                        let mut other = self.players[other_id];
+                        self.blast_hit(&mut other, target, player_id, events);
+                        self.players[other_id] = other;
+                        continue
+                    }
+                    Ok(CellState::PowerUp { power_up: PowerUp::Bomb }) if self.rules.blast.chain && !detonated.contains(&target) => {
+                        detonated.push(target);
+                        pending.push_back(target);
+                    }
+                    _ => {}
+                }
+                if let Ok(true) = self.explode_cell(target) {
+                    cleared_cells.push(target);
+                }
+            }
+            record(events, GameEvent::BombExploded { player_id, position, cleared_cells });
         }
     }
 
-    fn explode_cell(&mut self, position: Position) -> Result<(), ()> {
+    /// Damages `player`, caught at `position` in a blast set off by `owner_id`. A player who
+    /// dies leaves their cell as trail.
+    fn blast_hit(&mut self, player: &mut Player, position: Position, owner_id: usize,
+                 events: &mut Option<&mut Vec<GameEvent>>) {
+        let player_id = player.get_id();
+        if player.take_damage().is_err() {
+            return
+        }
+        record(events, hit_event(player_id, position, player, DeathCause::Blast { owner_id }));
+        if player.get_state() == PlayerState::Dead {
+            trace!(player_id, %position, "player died");
+            let _ = self.leave_trail(player_id, position);
+        }
+    }
+
+    /// Clears `position` unless it is empty or occupied, returning whether it did.
+    fn explode_cell(&mut self, position: Position) -> Result<bool, ()> {
         let cell = match self.get_cell(position) {
             Ok(cell) => { cell }
             Err(_) => { return Err(()) }
         };
         match cell.get_state() {
             CellState::PowerUp { .. } => {
-                self.update_cell_state(position, CellState::Empty)
+                self.update_cell_state(position, CellState::Empty).map(|_| true)
             }
             CellState::Wall => {
-                self.update_cell_state(position, CellState::Empty)
+                self.update_cell_state(position, CellState::Empty).map(|_| true)
             }
             CellState::Owned { .. } => {
-                self.update_cell_state(position, CellState::Empty)
+                self.update_cell_state(position, CellState::Empty).map(|_| true)
             }
-            _ => { Ok(()) }
+            _ => { Ok(false) }
         }
     }
 }
 
+/// What running into a wall or trail does to `player` under `collision`.
+fn crash(player: &mut Player, collision: Collision) {
+    match collision {
+        Collision::Damage => { player.take_damage().unwrap() }
+        Collision::Kill => { player.set_state(PlayerState::Dead) }
+    }
+}
+
+fn record(events: &mut Option<&mut Vec<GameEvent>>, event: GameEvent) {
+    if let Some(events) = events.as_deref_mut() {
+        events.push(event);
+    }
+}
+
+/// What `player` running into something at `position` amounts to, once damage is taken.
+fn hit_event(player_id: usize, position: Position, player: &Player, cause: DeathCause) -> GameEvent {
+    match player.get_state() {
+        PlayerState::Alive { armor, .. } => { GameEvent::ArmorAbsorbedHit { player_id, position, armor_left: armor } }
+        PlayerState::Dead => { GameEvent::PlayerDied { player_id, cause } }
+    }
+}
+
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut print_string = String::new();
-        for row in &self.cells {
-            for cell in row {
+        for (i_row, row) in self.cells.iter().enumerate() {
+            if self.grid == Grid::Hex && i_row % 2 == 1 {
+                print_string.push(' ');
+            }
+            for (i_col, cell) in row.iter().enumerate() {
+                if self.grid == Grid::Hex && i_col > 0 {
+                    print_string.push(' ');
+                }
                 let cell_symbol = match &cell.state {
                     CellState::Empty => { '*' }
-                    CellState::PowerUp { power_up } => {
-                        match &power_up {
-                            PowerUp::DoubleSpeed { .. } => { 'S' }
-                            PowerUp::Armor => { 'A' }
-                            PowerUp::Bomb => { 'B' }
-                        }
-                    }
+                    CellState::PowerUp { power_up } => { power_up.symbol() }
                     CellState::Wall => { '#' }
                     CellState::Owned { .. } => { '#' }
                     CellState::Occupied { player_id } => { player_id.to_string().pop().unwrap() }
@@ -268,4 +829,220 @@ impl Display for Board {
         }
         write!(f, "{}", print_string)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blast::BlastShape;
+    use crate::player::ACTIONS;
+
+    fn position_of(board: &Board, player_id: usize) -> Position {
+        match board.players()[player_id].get_state() {
+            PlayerState::Alive { position, .. } => { position }
+            PlayerState::Dead => { panic!("player {} is dead", player_id) }
+        }
+    }
+
+    fn owned_cells(board: &Board, player_id: usize) -> Vec<Position> {
+        let mut owned = Vec::new();
+        for row in 0..board.height() {
+            for column in 0..board.width() {
+                let position = Position::new(row, column);
+                if board.get_cell(position).unwrap().get_state() == (CellState::Owned { player_id }) {
+                    owned.push(position);
+                }
+            }
+        }
+        owned
+    }
+
+    #[test]
+    fn hex_grids_that_wrap_rows_need_an_even_height() {
+        let mut board = Board::new_default(5, 6).unwrap();
+        board.set_grid(Grid::Hex).unwrap();
+        assert!(board.set_topology(Topology::Torus).is_err());
+        assert!(board.set_rules(Rules { topology: Topology::Torus, ..Rules::default() }).is_err());
+        board.set_topology(Topology::Cylinder).unwrap();
+
+        let mut board = Board::new_default(5, 6).unwrap();
+        board.set_topology(Topology::Torus).unwrap();
+        assert!(board.set_grid(Grid::Hex).is_err());
+
+        let mut board = Board::new_default(6, 6).unwrap();
+        board.set_grid(Grid::Hex).unwrap();
+        board.set_topology(Topology::Torus).unwrap();
+    }
+
+    #[test]
+    fn used_teleport_steps_ahead_from_the_target() {
+        let mut board = Board::new_default(6, 6).unwrap();
+        board.set_inventory_slots(1);
+        board.place_power_up(Position::new(0, 1), PowerUp::Teleport).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+        assert_eq!(board.players()[0].inventory().items().count(), 1);
+
+        let mut events = Vec::new();
+        let board = board.apply_action_with_events(0, Action::Use, &mut events).unwrap();
+        let target = events.iter()
+            .find_map(|event| match event {
+                GameEvent::Teleported { to, .. } => { Some(*to) }
+                _ => { None }
+            })
+            .unwrap();
+        let head = position_of(&board, 0);
+        assert_eq!(Ok(head), board.step(target, Action::Right));
+        assert_eq!(board.get_cell(head).unwrap().get_state(), CellState::Occupied { player_id: 0 });
+        let mut owned = owned_cells(&board, 0);
+        owned.sort();
+        let mut expected = vec![Position::new(0, 0), Position::new(0, 1), target];
+        expected.sort();
+        assert_eq!(owned, expected);
+    }
+
+    #[test]
+    fn freeze_spares_teammates() {
+        let spawns = [Position::new(0, 0), Position::new(0, 5), Position::new(5, 0), Position::new(5, 5)];
+        let mut board = Board::with_spawns(6, 6, &spawns).unwrap();
+        board.set_teams(&[0, 1, 0, 1]).unwrap();
+        board.place_power_up(Position::new(0, 1), PowerUp::Freeze).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+        let frozen: Vec<usize> = board.players().iter()
+            .map(|player| match player.get_state() {
+                PlayerState::Alive { frozen, .. } => { frozen }
+                PlayerState::Dead => { panic!("nobody should die") }
+            })
+            .collect();
+        assert_eq!(frozen, vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn hex_moves_undo_each_other_across_the_wrapped_seam() {
+        let mut board = Board::new_default(6, 5).unwrap();
+        board.set_grid(Grid::Hex).unwrap();
+        board.set_topology(Topology::Torus).unwrap();
+        let opposites = [
+            (Action::UpRight, Action::DownLeft),
+            (Action::UpLeft, Action::DownRight),
+            (Action::Right, Action::Left),
+        ];
+        for row in 0..board.height() {
+            for column in 0..board.width() {
+                let position = Position::new(row, column);
+                for (action, back) in opposites {
+                    let there = board.step(position, action).unwrap();
+                    assert_eq!(board.step(there, back), Ok(position), "{:?} from {}", action, position);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn relative_steering_turns_from_the_heading() {
+        let mut board = Board::new_default(5, 5).unwrap();
+        board.set_steering(Steering::Relative);
+        assert_eq!(board.legal_actions(0), ACTIONS.to_vec());
+        assert_eq!(board.relative_action(0, RelativeAction::Left), Err(()));
+
+        let board = board.apply_action(0, Action::Right).unwrap();
+        assert_eq!(board.legal_actions(0), vec![Action::Up, Action::Right, Action::Down]);
+        assert_eq!(board.relative_action(0, RelativeAction::Left), Ok(Action::Up));
+        assert_eq!(board.relative_action(0, RelativeAction::Straight), Ok(Action::Right));
+        assert_eq!(board.relative_action(0, RelativeAction::Right), Ok(Action::Down));
+        assert!(board.apply_action(0, Action::Left).is_err());
+    }
+
+    #[test]
+    fn relative_steering_on_hex_grids_turns_one_sixth() {
+        let mut board = Board::new_default(6, 6).unwrap();
+        board.set_grid(Grid::Hex).unwrap();
+        board.set_steering(Steering::Relative);
+        let board = board.apply_action(0, Action::Right).unwrap();
+        assert_eq!(board.legal_actions(0), vec![Action::UpRight, Action::Right, Action::DownRight]);
+    }
+
+    #[test]
+    fn chained_blasts_set_off_the_bombs_they_reach() {
+        for chain in [false, true] {
+            let mut board = Board::new_default(5, 7).unwrap();
+            let mut rules = board.rules();
+            rules.blast = Blast { shape: BlastShape::Plus, radius: 1, damage: false, chain };
+            board.set_rules(rules).unwrap();
+            board.place_power_up(Position::new(0, 1), PowerUp::Bomb).unwrap();
+            board.place_power_up(Position::new(0, 2), PowerUp::Bomb).unwrap();
+            board.place_power_up(Position::new(0, 3), PowerUp::Armor).unwrap();
+            board.place_wall(Position::new(2, 1)).unwrap();
+
+            let mut events = Vec::new();
+            let board = board.apply_action_with_events(0, Action::Right, &mut events).unwrap();
+            let explosions = events.iter().filter(|event| matches!(event, GameEvent::BombExploded { .. })).count();
+            let armor_left = board.get_cell(Position::new(0, 3)).unwrap().get_state() != CellState::Empty;
+            assert_eq!(board.get_cell(Position::new(0, 2)).unwrap().get_state(), CellState::Empty);
+            assert_eq!(board.get_cell(Position::new(2, 1)).unwrap().get_state(), CellState::Wall);
+            assert_eq!((explosions, armor_left), if chain { (2, false) } else { (1, true) });
+        }
+    }
+
+    #[test]
+    fn blasts_damage_players_in_reach() {
+        let mut board = Board::with_spawns(3, 5, &[Position::new(1, 0), Position::new(1, 3)]).unwrap();
+        let mut rules = board.rules();
+        rules.blast = "diamond:2:damage".parse().unwrap();
+        board.set_rules(rules).unwrap();
+        board.place_power_up(Position::new(1, 1), PowerUp::Bomb).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+        assert_eq!(board.players()[1].get_state(), PlayerState::Dead);
+        assert!(matches!(board.players()[0].get_state(), PlayerState::Alive { .. }));
+    }
+
+    #[test]
+    fn wrapped_blasts_hit_each_player_once() {
+        // Across two columns, both sideways offsets of the blast land on the same cell.
+        let mut board = Board::with_spawns(3, 2, &[Position::new(0, 0), Position::new(2, 0)]).unwrap();
+        let mut rules = board.rules();
+        rules.topology = Topology::Torus;
+        rules.blast = "square:1:damage".parse().unwrap();
+        board.set_rules(rules).unwrap();
+        board.place_power_up(Position::new(1, 0), PowerUp::Armor).unwrap();
+        board.place_power_up(Position::new(0, 1), PowerUp::Bomb).unwrap();
+        let board = board.apply_action(1, Action::Up).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+        assert!(matches!(board.players()[1].get_state(), PlayerState::Alive { armor: 0, .. }));
+    }
+
+    #[test]
+    fn closed_rings_wall_up_cells_armor_held() {
+        let mut board = Board::new_default(6, 6).unwrap();
+        board.set_sudden_death(Some(SuddenDeath { start_turn: 1, interval: 100 }));
+        board.place_power_up(Position::new(0, 1), PowerUp::Armor).unwrap();
+        let mut board = board.apply_action(0, Action::Right).unwrap();
+        board.end_turn();
+        assert!(matches!(board.players()[0].get_state(), PlayerState::Alive { armor: 0, .. }));
+        assert_eq!(board.get_cell(Position::new(0, 1)).unwrap().get_state(), CellState::Occupied { player_id: 0 });
+
+        let mut board = board.apply_action(0, Action::Down).unwrap();
+        board.end_turn();
+        assert_eq!(board.get_cell(Position::new(0, 1)).unwrap().get_state(), CellState::Wall);
+        assert!(matches!(board.players()[0].get_state(), PlayerState::Alive { .. }));
+    }
+
+    #[test]
+    fn fading_trails_clear_after_their_turns() {
+        let mut board = Board::new_default(4, 4).unwrap();
+        let mut rules = board.rules();
+        rules.trails = TrailPersistence::Fading { turns: 2 };
+        board.set_rules(rules).unwrap();
+
+        let mut board = board.apply_action(0, Action::Right).unwrap();
+        board.end_turn();
+        assert_eq!(owned_cells(&board, 0), vec![Position::new(0, 0)]);
+        let mut board = board.apply_action(1, Action::Left).unwrap();
+        board.end_turn();
+        assert_eq!(owned_cells(&board, 0), Vec::new());
+        assert_eq!(board.get_cell(Position::new(0, 0)).unwrap().get_state(), CellState::Empty);
+        assert_eq!(owned_cells(&board, 1), vec![Position::new(3, 3)]);
+
+        // A cleared trail can be crossed again.
+        let board = board.apply_action(0, Action::Left).unwrap();
+        assert_eq!(position_of(&board, 0), Position::new(0, 0));
+    }
+}
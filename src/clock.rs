@@ -0,0 +1,97 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How much thinking time each player gets, enforced by `Game`.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub enum TimeControl {
+    /// `base` on the clock at the start, plus `increment` after every move.
+    Fischer {
+        base: Duration,
+        increment: Duration
+    },
+    /// `limit` for every move, with nothing banked between moves.
+    PerMove {
+        limit: Duration
+    },
+}
+
+impl TimeControl {
+    /// What each clock shows before the first move.
+    pub fn initial(&self) -> Duration {
+        match self {
+            TimeControl::Fischer { base, .. } => { *base }
+            TimeControl::PerMove { limit } => { *limit }
+        }
+    }
+
+    /// A sensible amount of time to spend on the next move with `remaining` on the clock,
+    /// leaving a tenth spare for overhead.
+    pub fn move_budget(&self, remaining: Duration) -> Duration {
+        let budget = match self {
+            TimeControl::Fischer { increment, .. } => { (remaining / 20 + *increment).min(remaining) }
+            TimeControl::PerMove { .. } => { remaining }
+        };
+        budget * 9 / 10
+    }
+}
+
+/// Parses `<base>+<increment>` for Fischer clocks or `<limit>/move`, all in milliseconds.
+impl FromStr for TimeControl {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let millis = |s: &str| s.parse().map(Duration::from_millis).map_err(|_| ());
+        if let Some(limit) = s.strip_suffix("/move") {
+            return Ok(TimeControl::PerMove { limit: millis(limit)? })
+        }
+        let (base, increment) = s.split_once('+').ok_or(())?;
+        Ok(TimeControl::Fischer { base: millis(base)?, increment: millis(increment)? })
+    }
+}
+
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeControl::Fischer { base, increment } => {
+                write!(f, "{}+{}", base.as_millis(), increment.as_millis())
+            }
+            TimeControl::PerMove { limit } => { write!(f, "{}/move", limit.as_millis()) }
+        }
+    }
+}
+
+/// What happens to a move that comes back after the player's time ran out.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq)]
+pub enum OverrunPenalty {
+    /// The player is eliminated.
+    #[default]
+    Forfeit,
+    /// A random move that doesn't kill the player, if there is one, is played instead.
+    RandomMove,
+    /// The player carries on in the direction of their last move.
+    RepeatLast,
+}
+
+impl FromStr for OverrunPenalty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forfeit" => { Ok(OverrunPenalty::Forfeit) }
+            "random" => { Ok(OverrunPenalty::RandomMove) }
+            "repeat" => { Ok(OverrunPenalty::RepeatLast) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for OverrunPenalty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverrunPenalty::Forfeit => { write!(f, "forfeit") }
+            OverrunPenalty::RandomMove => { write!(f, "random") }
+            OverrunPenalty::RepeatLast => { write!(f, "repeat") }
+        }
+    }
+}
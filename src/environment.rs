@@ -0,0 +1,300 @@
+use std::thread;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::board::{Board, CellState};
+use crate::game::{Game, GameState};
+use crate::observation::{encode, encode_view, Observation};
+use crate::player::{Action, PlayerState};
+use crate::player_controller::{calc_zone_relative, ExternalController, PlayerController};
+use crate::position::Position;
+use crate::power_up::random_power_up;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RewardConfig {
+    /// Paid to agents on the winning team that were still alive this step.
+    pub win: f32,
+    pub loss: f32,
+    /// Paid to agents still alive when the game ends in a draw.
+    pub draw: f32,
+    /// Paid every step an agent survives.
+    pub survival: f32,
+    /// Multiplies the agent's Voronoi territory lead each step.
+    pub territory: f32,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        RewardConfig { win: 1.0, loss: -1.0, draw: 0.0, survival: 0.0, territory: 0.0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StepInfo {
+    pub turn: usize,
+    pub winner_id: Option<usize>,
+    pub winning_team: Option<usize>,
+    pub actions: Vec<(usize, Vec<Action>)>,
+    /// Set by `VecEnvironment` when it resets a finished episode, holding that episode's final
+    /// observations.
+    pub terminal_observations: Option<Vec<Observation>>,
+}
+
+pub type StepResult = (Vec<Observation>, Vec<f32>, bool, StepInfo);
+
+/// Drives a `Game` where some players are external agents. Observations, rewards and actions
+/// are indexed by agent, in player id order.
+pub struct Environment {
+    game: Game,
+    agent_ids: Vec<usize>,
+    reward: RewardConfig,
+    board_factory: fn(&mut StdRng) -> Board,
+    rng: StdRng,
+}
+
+#[allow(clippy::result_unit_err)]
+impl Environment {
+    /// `controllers` has one entry per player: `None` for an agent, or the controller that plays
+    /// that seat.
+    pub fn new(controllers: Vec<Option<Box<dyn PlayerController>>>,
+               board_factory: fn(&mut StdRng) -> Board,
+               reward: RewardConfig) -> Result<Self, ()> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let board = board_factory(&mut rng);
+        let mut agent_ids = Vec::new();
+        let mut player_controllers: Vec<Box<dyn PlayerController>> = Vec::new();
+        for (player_id, controller) in controllers.into_iter().enumerate() {
+            match controller {
+                Some(controller) => { player_controllers.push(controller) }
+                None => {
+                    agent_ids.push(player_id);
+                    player_controllers.push(Box::new(ExternalController {}));
+                }
+            }
+        }
+        let game = Game::new(board, player_controllers)?;
+        Ok(Environment { game, agent_ids, reward, board_factory, rng })
+    }
+
+    pub fn agent_ids(&self) -> &Vec<usize> {
+        &self.agent_ids
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+        self.rng = StdRng::seed_from_u64(seed);
+        let board = (self.board_factory)(&mut self.rng);
+        self.game.reset(board).unwrap();
+        self.observations()
+    }
+
+    /// Each agent's encoded view of the board, which under fog of war hides what they can't see
+    /// and marks what they have seen in the `KNOWN` plane.
+    pub fn observations(&self) -> Vec<Observation> {
+        self.agent_ids.iter()
+            .map(|agent_id| match self.game.view(*agent_id) {
+                Some(view) => { encode_view(view) }
+                None => { encode(self.game.board(), *agent_id) }
+            })
+            .collect()
+    }
+
+    /// Plays one round: every player moves once, agents using `actions`. An agent whose move
+    /// leaves the board is eliminated.
+    pub fn step(&mut self, actions: &[Action]) -> Result<StepResult, ()> {
+        if actions.len() != self.agent_ids.len() {
+            return Err(())
+        }
+        let was_alive: Vec<bool> = self.agent_ids.iter().map(|agent_id| self.is_alive(*agent_id)).collect();
+        let mut taken = Vec::new();
+        for _ in 0..self.game.board().players().len() {
+            let active_id = match self.game.active_id() {
+                Some(active_id) => { active_id }
+                None => { break }
+            };
+            let result = match self.agent_ids.iter().position(|agent_id| *agent_id == active_id) {
+                Some(agent) => { self.game.run_turn_with(|_, _| actions[agent]) }
+                None => { self.game.run_turn() }
+            };
+            match result {
+                Ok((player_id, actions)) => { taken.push((player_id, actions)) }
+                Err(_) => {
+                    self.game.forfeit(active_id)?;
+                    taken.push((active_id, Vec::new()));
+                }
+            }
+        }
+
+        let (done, winner_id, winning_team) = match self.game.state() {
+            GameState::Active { .. } => { (false, None, None) }
+            GameState::Over { winner_id, winning_team, .. } => { (true, *winner_id, *winning_team) }
+        };
+        let mut rewards = Vec::new();
+        for (agent, agent_id) in self.agent_ids.iter().enumerate() {
+            let reward = if was_alive[agent] && winning_team == Some(self.game.board().team(*agent_id)) {
+                self.reward.win
+            } else if done && winning_team.is_none() && self.is_alive(*agent_id) {
+                self.reward.draw
+            } else if !self.is_alive(*agent_id) || done {
+                if was_alive[agent] { self.reward.loss } else { 0.0 }
+            } else {
+                let territory = calc_zone_relative(self.game.board(), *agent_id) as f32;
+                self.reward.survival + self.reward.territory * territory
+            };
+            rewards.push(reward);
+        }
+
+        let turn = match self.game.state() {
+            GameState::Active { turn, .. } => { *turn }
+            GameState::Over { .. } => { 0 }
+        };
+        let info = StepInfo { turn, winner_id, winning_team, actions: taken, terminal_observations: None };
+        Ok((self.observations(), rewards, done, info))
+    }
+
+    fn is_alive(&self, player_id: usize) -> bool {
+        matches!(self.game.board().players()[player_id].get_state(), PlayerState::Alive { .. })
+    }
+}
+
+/// The default 10x10 board with a few power-ups scattered on empty cells.
+pub fn default_board(rng: &mut StdRng) -> Board {
+    let mut board = Board::new_default(10, 10).unwrap();
+    scatter_power_ups(&mut board, rng, 3);
+    board
+}
+
+pub fn scatter_power_ups(board: &mut Board, rng: &mut StdRng, count: usize) {
+    let mut empty = Vec::new();
+    for row in 0..board.height() {
+        for column in 0..board.width() {
+            let position = Position::new(row, column);
+            if let Ok(cell) = board.get_cell(position) {
+                if cell.get_state() == CellState::Empty {
+                    empty.push(position);
+                }
+            }
+        }
+    }
+    for _ in 0..count.min(empty.len()) {
+        let position = empty.swap_remove(rng.gen_range(0..empty.len()));
+        let power_up = random_power_up(rng);
+        let _ = board.place_power_up(position, power_up);
+    }
+}
+
+/// A batch of environments stepped together, spread over threads. Finished episodes reset
+/// automatically with the next seed in sequence.
+pub struct VecEnvironment {
+    environments: Vec<Environment>,
+    next_seed: u64,
+    threads: usize,
+}
+
+#[allow(clippy::result_unit_err)]
+impl VecEnvironment {
+    /// Runs on as many threads as the machine has cores, see `set_threads`.
+    pub fn new(environments: Vec<Environment>) -> Self {
+        let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+        VecEnvironment { environments, next_seed: 0, threads }
+    }
+
+    pub fn len(&self) -> usize {
+        self.environments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.environments.is_empty()
+    }
+
+    /// Steps and resets the environments on up to `threads` threads, or in order on the calling
+    /// thread for 1, as controllers that call back into Python need.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Vec<Vec<Observation>> {
+        self.next_seed = seed + self.environments.len() as u64;
+        self.run(|index, environment| environment.reset(seed + index as u64))
+    }
+
+    pub fn step(&mut self, actions: &[Vec<Action>]) -> Result<Vec<StepResult>, ()> {
+        if actions.len() != self.environments.len() {
+            return Err(())
+        }
+        let mut results = self.run(|index, environment| environment.step(&actions[index]))
+            .into_iter()
+            .collect::<Result<Vec<StepResult>, ()>>()?;
+        // Seeds go to finished episodes in environment order, however the threads ran.
+        let mut seeds = Vec::new();
+        for (_, _, done, _) in &results {
+            seeds.push(done.then(|| {
+                self.next_seed += 1;
+                self.next_seed - 1
+            }));
+        }
+        let resets = self.run(|index, environment| seeds[index].map(|seed| environment.reset(seed)));
+        for ((observations, _, _, info), reset) in results.iter_mut().zip(resets) {
+            if let Some(reset) = reset {
+                info.terminal_observations = Some(std::mem::replace(observations, reset));
+            }
+        }
+        Ok(results)
+    }
+
+    /// Calls `work` with each environment and its index, returning the results in order.
+    fn run<T: Send>(&mut self, work: impl Fn(usize, &mut Environment) -> T + Sync) -> Vec<T> {
+        if self.threads <= 1 {
+            return self.environments.iter_mut().enumerate().map(|(index, environment)| work(index, environment)).collect()
+        }
+        let chunk_size = self.environments.len().div_ceil(self.threads).max(1);
+        let work = &work;
+        thread::scope(|scope| {
+            let handles: Vec<_> = self.environments.chunks_mut(chunk_size).enumerate()
+                .map(|(chunk, environments)| scope.spawn(move || {
+                    environments.iter_mut().enumerate()
+                        .map(|(index, environment)| work(chunk * chunk_size + index, environment))
+                        .collect::<Vec<T>>()
+                }))
+                .collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player_controller::ClockwiseController;
+
+    fn batch(threads: usize) -> VecEnvironment {
+        let environments = (0..5)
+            .map(|_| Environment::new(vec![None, Some(Box::new(ClockwiseController {}))], default_board, RewardConfig::default()).unwrap())
+            .collect();
+        let mut batch = VecEnvironment::new(environments);
+        batch.set_threads(threads);
+        batch
+    }
+
+    #[test]
+    fn threads_do_not_change_the_batch() {
+        let mut in_order = batch(1);
+        let mut threaded = batch(3);
+        assert_eq!(in_order.reset(7), threaded.reset(7));
+        let moves = [Action::Right, Action::Down, Action::Down, Action::Left];
+        let mut finished = 0;
+        for step in 0..40 {
+            let actions: Vec<Vec<Action>> = (0..5).map(|index| vec![moves[(step + index) % moves.len()]]).collect();
+            let expected = in_order.step(&actions).unwrap();
+            let results = threaded.step(&actions).unwrap();
+            for (expected, result) in expected.iter().zip(&results) {
+                assert_eq!((&expected.0, &expected.1, expected.2), (&result.0, &result.1, result.2));
+                assert_eq!(expected.3.terminal_observations, result.3.terminal_observations);
+                finished += result.2 as usize;
+            }
+        }
+        assert!(finished > 0);
+    }
+}
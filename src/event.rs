@@ -0,0 +1,106 @@
+use crate::game::EndReason;
+use crate::player::Action;
+use crate::position::Position;
+use crate::power_up::PowerUp;
+
+/// Something that happened in a `Game`, in the order it happened. Observers registered with
+/// `Game::add_observer` receive every event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    /// `player_id` moved from `from` to `to`. Emitted before anything the move set off.
+    Moved {
+        player_id: usize,
+        action: Action,
+        from: Position,
+        to: Position
+    },
+    /// Picked up from the board, either straight into effect or into the inventory.
+    PowerUpCollected {
+        player_id: usize,
+        power_up: PowerUp,
+        position: Position
+    },
+    /// `power_up` took effect for `player_id`, who stands at `position`.
+    PowerUpActivated {
+        player_id: usize,
+        power_up: PowerUp,
+        position: Position
+    },
+    /// Armor saved `player_id` from a wall, a trail or a closing ring at `position`.
+    ArmorAbsorbedHit {
+        player_id: usize,
+        position: Position,
+        armor_left: usize
+    },
+    /// A bomb set off by `player_id` went off, clearing `cleared_cells` around `position`. Each
+    /// bomb in a chain reaction is reported on its own, in the order they went off.
+    BombExploded {
+        player_id: usize,
+        position: Position,
+        cleared_cells: Vec<Position>
+    },
+    /// An eraser cleared `cleared_cells`, the oldest of `player_id`'s trail.
+    TrailErased {
+        player_id: usize,
+        cleared_cells: Vec<Position>
+    },
+    /// Fading trails at `cleared_cells` ran out at the end of a turn.
+    TrailFaded {
+        cleared_cells: Vec<Position>
+    },
+    /// `player_id` teleported, leaving `from` as trail.
+    Teleported {
+        player_id: usize,
+        from: Position,
+        to: Position
+    },
+    /// `player_id` was frozen and missed their turn.
+    TurnSkipped {
+        player_id: usize
+    },
+    PlayerDied {
+        player_id: usize,
+        cause: DeathCause
+    },
+    GameEnded {
+        /// `None` for a draw, or when the winning team has several players.
+        winner_id: Option<usize>,
+        /// `None` for a draw.
+        winning_team: Option<usize>,
+        reason: EndReason
+    },
+}
+
+/// What eliminated a player.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DeathCause {
+    /// Ran into a wall without armor.
+    Wall,
+    /// Ran into `owner_id`'s trail, possibly their own, without armor.
+    Trail {
+        owner_id: usize
+    },
+    /// Ran into `other_id` where they stood.
+    Collision {
+        other_id: usize
+    },
+    /// Caught in a blast from a bomb set off by `owner_id`, possibly themselves.
+    Blast {
+        owner_id: usize
+    },
+    /// Caught in a ring closed by sudden death.
+    SuddenDeath,
+    /// Forfeited, or ran out of time under `OverrunPenalty::Forfeit`.
+    Forfeit,
+}
+
+/// Receives a `Game`'s events as they happen.
+pub trait GameObserver: Send {
+    fn on_event(&mut self, event: &GameEvent);
+}
+
+impl<F: FnMut(&GameEvent) + Send> GameObserver for F {
+    fn on_event(&mut self, event: &GameEvent) {
+        self(event)
+    }
+}
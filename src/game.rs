@@ -1,6 +1,18 @@
+use std::borrow::Cow;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tracing::{debug, debug_span};
 use crate::board::{Board};
+use crate::event::{DeathCause, GameEvent, GameObserver};
+use crate::clock::{OverrunPenalty, TimeControl};
 use crate::player::{Action, PlayerState};
-use crate::player_controller::PlayerController;
+use crate::player_controller::{random_safe_action, PlayerController};
+use crate::search_info::SearchInfo;
+use crate::worker::ThreadedController;
+use crate::turn_limit::Tiebreak;
+use crate::visibility::{PlayerView, UnknownCells, Visibility};
 
 #[derive(Debug, Clone)]
 pub enum GameState {
@@ -9,16 +21,45 @@ pub enum GameState {
         alive_ids: Vec<usize>
     },
     Over {
-        winner_id: usize
+        /// `None` for a draw, or when the winning team has several players.
+        winner_id: Option<usize>,
+        /// `None` for a draw.
+        winning_team: Option<usize>,
+        reason: EndReason
     }
 }
 
+/// Why a game ended.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EndReason {
+    /// Every player outside the winning team was eliminated or forfeited.
+    LastSurvivor,
+    /// The remaining players were eliminated together, a draw.
+    MutualElimination,
+    /// The board's turn limit ran out and `tiebreak` decided the result.
+    TurnLimit {
+        tiebreak: Tiebreak
+    },
+}
+
 pub struct Game {
     board: Board,
     player_controllers: Vec<Box<dyn PlayerController>>,
     state: GameState,
+    time_control: Option<TimeControl>,
+    overrun_penalty: OverrunPenalty,
+    /// Time left per player while `time_control` is set.
+    clocks: Vec<Duration>,
+    /// Picks replacement moves for `OverrunPenalty::RandomMove`.
+    rng: StdRng,
+    /// Per player, the opponent and move their controller predicted while pondering.
+    predictions: Vec<Option<(usize, Action)>>,
+    observers: Vec<Box<dyn GameObserver>>,
+    /// Each player's view of the board, kept while its `Visibility` hides part of it.
+    views: Vec<PlayerView>,
 }
 
+#[allow(clippy::result_unit_err)]
 impl Game {
     pub fn new_default(player_controllers: [Box<dyn PlayerController>; 2]) -> Self {
         let board = Board::new_default(10, 10).unwrap();
@@ -26,10 +67,30 @@ impl Game {
         Game::new(board, Vec::from(player_controllers)).unwrap()
     }
 
+    /// A game on `board` with one controller per player, or `Err` if the counts differ or the
+    /// board has no players.
     pub fn new(board: Board, player_controllers: Vec<Box<dyn PlayerController>>) -> Result<Self, ()> {
-        if board.players().len() != player_controllers.len() {
+        if board.players().is_empty() || board.players().len() != player_controllers.len() {
             return Err(())
         }
+        let state = Game::initial_state(&board);
+        let mut game = Game {
+            board,
+            player_controllers,
+            state,
+            time_control: None,
+            overrun_penalty: OverrunPenalty::Forfeit,
+            clocks: Vec::new(),
+            rng: StdRng::from_entropy(),
+            predictions: Vec::new(),
+            observers: Vec::new(),
+            views: Vec::new(),
+        };
+        game.reset_views();
+        Ok(game)
+    }
+
+    fn initial_state(board: &Board) -> GameState {
         let mut alive_ids = Vec::new();
         for (player_id, player) in board.players().iter().enumerate() {
             match player.get_state() {
@@ -39,40 +100,229 @@ impl Game {
                 }
             }
         }
-        Ok(Game {
-            board,
-            player_controllers,
-            state: GameState::Active {
-                turn: 0,
-                alive_ids
+        GameState::Active {
+            turn: 0,
+            alive_ids
+        }
+    }
+
+    /// Starts a fresh game on `board`, keeping the same controllers. Returns `Err` if the board
+    /// does not have one player per controller.
+    pub fn reset(&mut self, board: Board) -> Result<(), ()> {
+        if board.players().len() != self.player_controllers.len() {
+            return Err(())
+        }
+        self.state = Game::initial_state(&board);
+        self.board = board;
+        self.reset_clocks();
+        self.predictions.clear();
+        self.reset_views();
+        Ok(())
+    }
+
+    /// Starts every player's view afresh on the current board.
+    fn reset_views(&mut self) {
+        self.views = match self.board.rules().visibility {
+            Visibility::Full => { Vec::new() }
+            _ => { (0..self.board.players().len()).map(|player_id| PlayerView::new(&self.board, player_id)).collect() }
+        };
+    }
+
+    /// Shows every player's view the board as it now stands.
+    fn update_views(&mut self) {
+        for view in &mut self.views {
+            view.update(&self.board);
+        }
+    }
+
+    /// Puts every player on `time_control`, starting their clocks afresh. Moves that overrun
+    /// are punished with `penalty`. A controller that never returns still stalls the game.
+    pub fn set_time_control(&mut self, time_control: Option<TimeControl>, penalty: OverrunPenalty) {
+        self.time_control = time_control;
+        self.overrun_penalty = penalty;
+        self.reset_clocks();
+    }
+
+    /// Moves every controller onto its own worker thread, replacing moves that take longer
+    /// than `deadline` with a random safe one. See `ThreadedController`.
+    pub fn run_controllers_on_threads(&mut self, deadline: Duration) {
+        self.player_controllers = std::mem::take(&mut self.player_controllers).into_iter()
+            .map(|controller| Box::new(ThreadedController::new(controller, deadline)) as Box<dyn PlayerController>)
+            .collect();
+    }
+
+    /// Starts sending every controller's `SearchInfo` reports to the returned receiver,
+    /// replacing any earlier subscription. Controllers that don't search send nothing.
+    pub fn subscribe_search_info(&mut self) -> Receiver<SearchInfo> {
+        let (sink, receiver) = channel();
+        for controller in &mut self.player_controllers {
+            controller.set_info_sink(sink.clone());
+        }
+        receiver
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.time_control
+    }
+
+    /// The time `player_id` has left, if the game runs on a clock.
+    pub fn remaining_time(&self, player_id: usize) -> Option<Duration> {
+        self.clocks.get(player_id).copied()
+    }
+
+    fn reset_clocks(&mut self) {
+        self.clocks = match self.time_control {
+            Some(time_control) => { vec![time_control.initial(); self.board.players().len()] }
+            None => { Vec::new() }
+        };
+    }
+
+    /// Takes `elapsed` off `player_id`'s clock, returning whether they ran out of time.
+    fn charge_clock(&mut self, player_id: usize, elapsed: Duration) -> bool {
+        let (time_control, remaining) = match (self.time_control, self.clocks.get_mut(player_id)) {
+            (Some(time_control), Some(remaining)) => { (time_control, remaining) }
+            _ => { return false }
+        };
+        match time_control {
+            TimeControl::Fischer { increment, .. } => {
+                let overrun = elapsed > *remaining;
+                *remaining = remaining.saturating_sub(elapsed) + increment;
+                overrun
             }
-        })
+            TimeControl::PerMove { limit } => { elapsed > limit }
+        }
+    }
+
+    /// The move played in place of one that overran, or `None` if the player forfeits.
+    fn overrun_action(&mut self, player_id: usize) -> Option<Action> {
+        match self.overrun_penalty {
+            OverrunPenalty::Forfeit => { None }
+            OverrunPenalty::RandomMove => { Some(random_safe_action(&self.board, player_id, &mut self.rng)) }
+            OverrunPenalty::RepeatLast => {
+                // Before their first move there is nothing to repeat, so they get a random one.
+                match self.board.players()[player_id].get_state() {
+                    PlayerState::Alive { heading: Some(heading), .. } => { Some(heading) }
+                    _ => { Some(random_safe_action(&self.board, player_id, &mut self.rng)) }
+                }
+            }
+        }
     }
 
     pub fn state(&self) -> &GameState {
         &self.state
     }
 
+    /// The true board. Under fog of war controllers only see `player_board`.
     pub fn board(&self) -> &Board {
         &self.board
     }
 
+    /// `player_id`'s view of the board, or `None` when the board's `Visibility` is full.
+    pub fn view(&self, player_id: usize) -> Option<&PlayerView> {
+        self.views.get(player_id)
+    }
+
+    /// The board as `player_id` knows it, taking cells they have never seen to be as `unknown`
+    /// says. This is the true board when the board's `Visibility` is full.
+    pub fn player_board(&self, player_id: usize, unknown: UnknownCells) -> Cow<'_, Board> {
+        seen_board(&self.board, &self.views, player_id, unknown)
+    }
+
+    /// The id of the player who moves on the next call to `run_turn`.
+    pub fn active_id(&self) -> Option<usize> {
+        match &self.state {
+            GameState::Active { turn, .. } => { Some(turn % self.board.players().len()) }
+            GameState::Over { .. } => { None }
+        }
+    }
+
     pub fn run_turn(&mut self) -> Result<(usize, Vec<Action>), ()> {
+        if let (Some(time_control), Some(active_id)) = (self.time_control, self.active_id()) {
+            self.player_controllers[active_id].set_clock(self.clocks[active_id], time_control);
+        }
+        let mut controllers = std::mem::take(&mut self.player_controllers);
+        let unknown: Vec<UnknownCells> = controllers.iter().map(|controller| controller.unknown_cells()).collect();
+        let result = self.play_turn(&unknown, |board, player_id| {
+            controllers[player_id].get_action(board, player_id)
+        });
+        self.player_controllers = controllers;
+        if let Ok((player_id, actions)) = &result {
+            self.update_ponderers(*player_id, actions);
+        }
+        result
+    }
+
+    /// Tells controllers that predicted `player_id`'s move whether they were right, then lets
+    /// `player_id`'s own controller predict the reply of whoever moves next, if it can ponder.
+    fn update_ponderers(&mut self, player_id: usize, actions: &[Action]) {
+        self.predictions.resize(self.player_controllers.len(), None);
+        for ponderer_id in 0..self.predictions.len() {
+            match self.predictions[ponderer_id] {
+                Some((opponent_id, predicted)) if opponent_id == player_id => {
+                    self.predictions[ponderer_id] = None;
+                    if actions.first() == Some(&predicted) {
+                        self.player_controllers[ponderer_id].ponder_hit();
+                    } else {
+                        self.player_controllers[ponderer_id].ponder_miss();
+                    }
+                }
+                _ => {}
+            }
+        }
+        let alive_ids = match &self.state {
+            GameState::Active { alive_ids, .. } if alive_ids.contains(&player_id) => { alive_ids }
+            _ => { return }
+        };
+        let n_players = self.player_controllers.len();
+        let opponent_id = (1..n_players)
+            .map(|offset| (player_id + offset) % n_players)
+            .find(|id| alive_ids.contains(id));
+        if !self.player_controllers[player_id].can_ponder() {
+            return
+        }
+        if let Some(opponent_id) = opponent_id {
+            let unknown = self.player_controllers[player_id].unknown_cells();
+            let board = seen_board(&self.board, &self.views, player_id, unknown);
+            self.predictions[player_id] = self.player_controllers[player_id]
+                .predict(&board, player_id, opponent_id)
+                .map(|action| (opponent_id, action));
+        }
+    }
+
+    /// Runs a turn like `run_turn`, but asks `get_action` for the active player's moves instead
+    /// of its controller. Each call is timed against the player's clock. Under fog of war
+    /// `get_action` is shown the player's view, with unseen cells empty.
+    pub fn run_turn_with<F>(&mut self, get_action: F) -> Result<(usize, Vec<Action>), ()>
+        where F: FnMut(&Board, usize) -> Action
+    {
+        self.play_turn(&[], get_action)
+    }
+
+    /// Runs a turn, showing each player cells they have never seen as `unknown` says, or
+    /// empty if it has no entry for them.
+    fn play_turn<F>(&mut self, unknown: &[UnknownCells], mut get_action: F) -> Result<(usize, Vec<Action>), ()>
+        where F: FnMut(&Board, usize) -> Action
+    {
         match &self.state {
             GameState::Active { turn, alive_ids } => {
                 let turn = *turn;
-                let active_id = turn % self.player_controllers.len();
+                let active_id = turn % self.board.players().len();
+                let _span = debug_span!("turn", turn, player_id = active_id).entered();
                 if !alive_ids.contains(&active_id) {
-                    self.state = GameState::Active {
-                        turn: turn + 1,
-                        alive_ids: alive_ids.clone()
-                    };
+                    let alive_ids = alive_ids.clone();
+                    self.end_turn(turn, alive_ids);
+                    return Ok((active_id, Vec::new()))
+                }
+                if self.board.skip_frozen_turn(active_id) {
+                    let alive_ids = alive_ids.clone();
+                    self.emit(vec![GameEvent::TurnSkipped { player_id: active_id }]);
+                    self.end_turn(turn, alive_ids);
                     return Ok((active_id, Vec::new()))
                 }
-                let n_actions: u8 = match self.board.players()[active_id].get_state() {
-                    PlayerState::Alive { position: _, boost, armor: _ } => {
+                let n_actions = match self.board.players()[active_id].get_state() {
+                    PlayerState::Alive { boost, .. } => {
                         if boost > 0 {
-                            2
+                            self.board.rules().boost_moves.max(1)
                         } else {
                             1
                         }
@@ -80,50 +330,154 @@ impl Game {
                     PlayerState::Dead => { return Err(()) }
                 };
                 let mut actions = Vec::new();
+                let mut alive_ids = alive_ids.clone();
                 for _ in 0..n_actions {
-                    let mut controller = self.player_controllers.get_mut(active_id).unwrap();
-                    let action = controller.get_action(&self.board, active_id);
-                    match self.apply_action(action, active_id) {
-                        Ok(alive_ids) => {
-                            actions.push(action);
-                            if alive_ids.len() == 1 {
-                                self.state = GameState::Over {
-                                    winner_id: alive_ids[0]
-                                };
-                                break;
-                            } else {
-                                self.state = GameState::Active {
-                                    turn: turn + 1,
-                                    alive_ids
-                                }
+                    let board = seen_board(&self.board, &self.views, active_id, unknown.get(active_id).copied().unwrap_or_default());
+                    let started = Instant::now();
+                    let mut action = get_action(&board, active_id);
+                    let elapsed = started.elapsed();
+                    if self.charge_clock(active_id, elapsed) {
+                        debug!(player_id = active_id, ?elapsed, penalty = %self.overrun_penalty, "clock overrun");
+                        match self.overrun_action(active_id) {
+                            Some(replacement) => { action = replacement }
+                            None => {
+                                self.forfeit(active_id)?;
+                                return Ok((active_id, actions))
                             }
                         }
-                        Err(err) => { return Err(err) }
+                    }
+                    alive_ids = self.apply_action(action, active_id)?;
+                    actions.push(action);
+                    if self.finish_if_decided(&alive_ids) {
+                        return Ok((active_id, actions))
+                    }
+                    self.state = GameState::Active {
+                        turn,
+                        alive_ids: alive_ids.clone()
+                    };
+                    if !alive_ids.contains(&active_id) {
+                        break
                     }
                 }
+                self.end_turn(turn, alive_ids);
                 Ok((active_id, actions))
             }
             GameState::Over { .. } => { Err(()) }
         }
     }
 
+    /// Moves on from `turn`, letting the board apply end-of-turn rules such as sudden death, and
+    /// ends the game if it has reached the board's turn limit.
+    fn end_turn(&mut self, turn: usize, alive_ids: Vec<usize>) {
+        let events = self.board.end_turn();
+        self.update_views();
+        self.emit(events);
+        let survivors: Vec<usize> = alive_ids.iter().copied()
+            .filter(|id| self.board.players()[*id].get_state() != PlayerState::Dead)
+            .collect();
+        if self.finish_if_decided(&survivors) {
+            return
+        }
+        match self.board.turn_limit() {
+            Some(limit) if turn + 1 >= limit.turns => {
+                let winning_team = limit.tiebreak.winner(&self.board, &survivors);
+                self.finish(winning_team, EndReason::TurnLimit { tiebreak: limit.tiebreak });
+            }
+            _ => {
+                self.state = GameState::Active {
+                    turn: turn + 1,
+                    alive_ids: survivors
+                };
+            }
+        }
+    }
+
+    /// Ends the game if the players in `alive_ids` are all on one team, or none are left.
+    fn finish_if_decided(&mut self, alive_ids: &[usize]) -> bool {
+        let mut teams: Vec<usize> = alive_ids.iter().map(|id| self.board.team(*id)).collect();
+        teams.dedup();
+        match teams[..] {
+            [] => { self.finish(None, EndReason::MutualElimination) }
+            [team] => { self.finish(Some(team), EndReason::LastSurvivor) }
+            _ => { return false }
+        }
+        true
+    }
+
+    /// Ends the game in a win for `winning_team`, or a draw for `None`.
+    fn finish(&mut self, winning_team: Option<usize>, reason: EndReason) {
+        let members: Vec<usize> = (0..self.board.players().len())
+            .filter(|id| Some(self.board.team(*id)) == winning_team)
+            .collect();
+        let winner_id = match members[..] {
+            [winner_id] => { Some(winner_id) }
+            _ => { None }
+        };
+        self.state = GameState::Over { winner_id, winning_team, reason };
+        self.emit(vec![GameEvent::GameEnded { winner_id, winning_team, reason }]);
+    }
+
+    /// Registers `observer` to receive every event from now on, see `GameEvent`.
+    pub fn add_observer(&mut self, observer: Box<dyn GameObserver>) {
+        self.observers.push(observer);
+    }
+
+    fn emit(&mut self, events: Vec<GameEvent>) {
+        for event in events {
+            debug!(?event, "event");
+            for observer in &mut self.observers {
+                observer.on_event(&event);
+            }
+        }
+    }
+
+    /// Eliminates `player_id`, passing the turn on if it was theirs.
+    pub fn forfeit(&mut self, player_id: usize) -> Result<(), ()> {
+        match &self.state {
+            GameState::Active { turn, alive_ids } => {
+                if !alive_ids.contains(&player_id) {
+                    return Err(())
+                }
+                let turn = *turn;
+                let alive_ids: Vec<usize> = alive_ids.iter().copied().filter(|id| *id != player_id).collect();
+                self.board = self.board.eliminate_player(player_id)?;
+                self.update_views();
+                self.emit(vec![GameEvent::PlayerDied { player_id, cause: DeathCause::Forfeit }]);
+                let n_players = self.board.players().len();
+                if self.finish_if_decided(&alive_ids) {
+                    return Ok(())
+                }
+                if turn % n_players == player_id {
+                    self.end_turn(turn, alive_ids);
+                } else {
+                    self.state = GameState::Active {
+                        turn,
+                        alive_ids
+                    };
+                }
+                Ok(())
+            }
+            GameState::Over { .. } => { Err(()) }
+        }
+    }
+
     fn apply_action(&mut self, action: Action, active_id: usize) -> Result<Vec<usize>,()> {
         match &self.state {
             GameState::Active { turn: _, alive_ids } => {
                 if !alive_ids.contains(&active_id) {
                     return Err(())
                 }
-                match self.board.apply_action(active_id, action) {
+                let mut events = Vec::new();
+                match self.board.apply_action_with_events(active_id, action, &mut events) {
                     Ok(board) => {
                         self.board = board;
-                        Ok(match self.board.players()[active_id].get_state() {
-                            PlayerState::Alive { .. } => { alive_ids.clone() }
-                            PlayerState::Dead => {
-                                let mut new_ids = alive_ids.clone();
-                                new_ids.remove(active_id);
-                                new_ids
-                            }
-                        })
+                        // Blasts may take out other players along with the mover.
+                        let alive_ids = alive_ids.iter().copied()
+                            .filter(|id| self.board.players()[*id].get_state() != PlayerState::Dead)
+                            .collect();
+                        self.update_views();
+                        self.emit(events);
+                        Ok(alive_ids)
                     }
                     Err(err) => { Err(err) }
                 }
@@ -131,4 +485,103 @@ impl Game {
             GameState::Over { .. } => { Err(()) }
         }
     }
-}
\ No newline at end of file
+}
+
+/// `board` as `player_id` sees it through their entry in `views`, or all of it without one.
+fn seen_board<'a>(board: &'a Board, views: &'a [PlayerView], player_id: usize, unknown: UnknownCells) -> Cow<'a, Board> {
+    match views.get(player_id) {
+        Some(view) => { view.assume(unknown) }
+        None => { Cow::Borrowed(board) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use crate::player_controller::ClockwiseController;
+    use crate::position::Position;
+
+    fn four_player_game(teams: &[usize]) -> Game {
+        let spawns = [Position::new(0, 0), Position::new(0, 7), Position::new(7, 7), Position::new(7, 0)];
+        let mut board = Board::with_spawns(8, 8, &spawns).unwrap();
+        board.set_teams(teams).unwrap();
+        let controllers: Vec<Box<dyn PlayerController>> = (0..4)
+            .map(|_| Box::new(ClockwiseController {}) as Box<dyn PlayerController>)
+            .collect();
+        Game::new(board, controllers).unwrap()
+    }
+
+    #[test]
+    fn a_team_wins_once_every_opponent_is_out() {
+        let mut game = four_player_game(&[0, 1, 0, 1]);
+        game.forfeit(1).unwrap();
+        game.forfeit(2).unwrap();
+        assert!(matches!(game.state(), GameState::Active { .. }));
+        game.forfeit(3).unwrap();
+        match game.state() {
+            GameState::Over { winner_id, winning_team, reason } => {
+                assert_eq!((*winner_id, *winning_team, *reason), (None, Some(0), EndReason::LastSurvivor));
+            }
+            GameState::Active { .. } => { panic!("team 0 should have won") }
+        }
+    }
+
+    #[test]
+    fn a_team_of_one_names_its_winner() {
+        let mut game = four_player_game(&[0, 1, 1, 1]);
+        game.forfeit(0).unwrap();
+        assert!(matches!(game.state(), GameState::Over { winner_id: None, winning_team: Some(1), .. }));
+
+        let mut game = four_player_game(&[1, 0, 0, 0]);
+        for player_id in 1..4 {
+            game.forfeit(player_id).unwrap();
+        }
+        assert!(matches!(game.state(), GameState::Over { winner_id: Some(0), winning_team: Some(1), .. }));
+    }
+
+    #[test]
+    fn games_need_players() {
+        let board = Board::with_spawns(4, 4, &[]).unwrap();
+        assert!(board.players().is_empty());
+        assert!(Game::new(board.clone(), Vec::new()).is_err());
+
+        let mut game = four_player_game(&[0, 1, 2, 3]);
+        assert!(game.reset(board).is_err());
+        assert!(matches!(game.state(), GameState::Active { turn: 0, .. }));
+    }
+
+    struct CountingController {
+        predictions: Arc<AtomicUsize>,
+    }
+
+    impl PlayerController for CountingController {
+        fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
+            ClockwiseController {}.get_action(board, player_id)
+        }
+
+        fn predict(&mut self, _board: &Board, _player_id: usize, _opponent_id: usize) -> Option<Action> {
+            self.predictions.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    #[test]
+    fn only_controllers_that_ponder_are_asked_to_predict() {
+        for threaded in [false, true] {
+            let predictions = Arc::new(AtomicUsize::new(0));
+            let controllers: Vec<Box<dyn PlayerController>> = (0..2)
+                .map(|_| Box::new(CountingController { predictions: predictions.clone() }) as Box<dyn PlayerController>)
+                .collect();
+            let mut game = Game::new(Board::new_default(6, 6).unwrap(), controllers).unwrap();
+            if threaded {
+                game.run_controllers_on_threads(Duration::from_secs(1));
+            }
+            game.run_turn().unwrap();
+            game.run_turn().unwrap();
+            drop(game);
+            assert_eq!(predictions.load(Ordering::Relaxed) > 0, threaded);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::blast::BlastShape;
+use crate::player::{Action, ACTIONS, HEX_ACTIONS};
+use crate::position::Position;
+use crate::topology::Topology;
+
+/// The shape of the cells. Hex boards store pointy-topped hexes in "odd-r" offset layout: odd
+/// rows are shifted half a cell to the right, and moves are worked out in axial coordinates.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Grid {
+    #[default]
+    Square,
+    Hex,
+}
+
+#[allow(clippy::result_unit_err)]
+impl Grid {
+    /// Whether a board of `height` rows can use this grid with `topology`. Hex rows alternate
+    /// their shift, so wrapping an odd number of them would join two rows shifted the same way.
+    pub fn fits(&self, topology: Topology, height: usize) -> bool {
+        !(*self == Grid::Hex && topology.wraps_rows() && height % 2 == 1)
+    }
+
+    /// The moves available on this grid.
+    pub fn actions(&self) -> &'static [Action] {
+        match self {
+            Grid::Square => { &ACTIONS }
+            Grid::Hex => { &HEX_ACTIONS }
+        }
+    }
+
+    /// The (row, column) offset `action` moves from `position`, or `Err` if it is not a move on
+    /// this grid. Hex offsets depend on the parity of the row.
+    pub fn step(&self, action: Action, position: Position) -> Result<(isize, isize), ()> {
+        match self {
+            Grid::Square => {
+                if ACTIONS.contains(&action) { Ok(action.offset()) } else { Err(()) }
+            }
+            Grid::Hex => {
+                let (q_offset, r_offset) = match action {
+                    Action::Right => { (1, 0) }
+                    Action::Left => { (-1, 0) }
+                    Action::UpRight => { (1, -1) }
+                    Action::UpLeft => { (0, -1) }
+                    Action::DownRight => { (0, 1) }
+                    Action::DownLeft => { (-1, 1) }
+                    Action::Up | Action::Down | Action::Use => { return Err(()) }
+                };
+                let row = position.row as isize;
+                let column = position.column as isize;
+                let q = column - (row - (row & 1)) / 2;
+                let new_row = row + r_offset;
+                let new_column = q + q_offset + (new_row - (new_row & 1)) / 2;
+                Ok((r_offset, new_column - column))
+            }
+        }
+    }
+
+    /// The (row, column) offsets of the cells a blast of `shape` and `radius` reaches from
+    /// `position`, not counting `position` itself. Hex blasts are worked out in axial
+    /// coordinates, where the straight lines are those of the six moves.
+    pub fn blast_offsets(&self, position: Position, shape: BlastShape, radius: usize) -> Vec<(isize, isize)> {
+        let radius = radius as isize;
+        let mut offsets = Vec::new();
+        for row_offset in -radius..=radius {
+            for col_offset in -radius..=radius {
+                if (row_offset, col_offset) == (0, 0) {
+                    continue
+                }
+                match self {
+                    Grid::Square => {
+                        let reached = match shape {
+                            BlastShape::Square => { true }
+                            BlastShape::Plus => { row_offset == 0 || col_offset == 0 }
+                            BlastShape::Diamond => { row_offset.abs() + col_offset.abs() <= radius }
+                        };
+                        if reached {
+                            offsets.push((row_offset, col_offset));
+                        }
+                    }
+                    Grid::Hex => {
+                        // Here `col_offset` is the offset in q, the axial column.
+                        let q_offset = col_offset;
+                        let reached = match shape {
+                            BlastShape::Square | BlastShape::Diamond => {
+                                (q_offset + row_offset).abs() <= radius
+                            }
+                            BlastShape::Plus => {
+                                q_offset == 0 || row_offset == 0 || q_offset + row_offset == 0
+                            }
+                        };
+                        if reached {
+                            let row = position.row as isize;
+                            let q = position.column as isize - (row - (row & 1)) / 2;
+                            let new_row = row + row_offset;
+                            let new_column = q + q_offset + (new_row - (new_row & 1)) / 2;
+                            offsets.push((row_offset, new_column - position.column as isize));
+                        }
+                    }
+                }
+            }
+        }
+        offsets
+    }
+}
+
+impl FromStr for Grid {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => { Ok(Grid::Square) }
+            "hex" => { Ok(Grid::Hex) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Grid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Grid::Square => { write!(f, "square") }
+            Grid::Hex => { write!(f, "hex") }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_neighbours_depend_on_row_parity() {
+        let even = Position::new(2, 2);
+        let odd = Position::new(3, 2);
+        let steps = |position| HEX_ACTIONS.map(|action| Grid::Hex.step(action, position).unwrap());
+        // Clockwise from the upper right; odd rows are shifted half a cell to the right.
+        assert_eq!(steps(even), [(-1, 0), (0, 1), (1, 0), (1, -1), (0, -1), (-1, -1)]);
+        assert_eq!(steps(odd), [(-1, 1), (0, 1), (1, 1), (1, 0), (0, -1), (-1, 0)]);
+        assert_eq!(Grid::Hex.step(Action::Up, even), Err(()));
+        assert_eq!(Grid::Square.step(Action::UpRight, even), Err(()));
+    }
+
+    #[test]
+    fn blast_shapes_reach_the_expected_number_of_cells() {
+        let center = Position::new(5, 5);
+        let reached = |grid: Grid, shape, radius| grid.blast_offsets(center, shape, radius).len();
+        assert_eq!(reached(Grid::Square, BlastShape::Square, 1), 8);
+        assert_eq!(reached(Grid::Square, BlastShape::Plus, 2), 8);
+        assert_eq!(reached(Grid::Square, BlastShape::Diamond, 2), 12);
+        assert_eq!(reached(Grid::Hex, BlastShape::Square, 1), 6);
+        assert_eq!(reached(Grid::Hex, BlastShape::Plus, 2), 12);
+        assert_eq!(reached(Grid::Hex, BlastShape::Diamond, 2), 18);
+    }
+
+    #[test]
+    fn hex_blasts_reach_the_neighbours() {
+        for position in [Position::new(2, 2), Position::new(3, 2)] {
+            let mut neighbours: Vec<(isize, isize)> = HEX_ACTIONS.iter()
+                .map(|action| Grid::Hex.step(*action, position).unwrap())
+                .collect();
+            let mut reached = Grid::Hex.blast_offsets(position, BlastShape::Square, 1);
+            neighbours.sort();
+            reached.sort();
+            assert_eq!(reached, neighbours);
+        }
+    }
+}
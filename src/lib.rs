@@ -0,0 +1,29 @@
+pub mod bench;
+pub mod board;
+pub mod player;
+pub mod position;
+pub mod topology;
+pub mod grid;
+pub mod blast;
+pub mod rules;
+pub mod sudden_death;
+pub mod turn_limit;
+pub mod visibility;
+pub mod map_generator;
+pub mod power_up;
+pub mod player_controller;
+pub mod clock;
+pub mod event;
+pub mod game;
+pub mod logging;
+pub mod observation;
+pub mod environment;
+pub mod npy;
+pub mod network;
+pub mod search_info;
+pub mod selfplay;
+pub mod transposition;
+pub mod tune;
+pub mod worker;
+#[cfg(feature = "python")]
+pub mod python;
@@ -0,0 +1,52 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+/// How log lines are written to stderr.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq)]
+pub enum LogFormat {
+    /// One human-readable line per event, prefixed with its spans.
+    #[default]
+    Text,
+    /// One JSON object per event, for machine analysis.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => { Ok(LogFormat::Text) }
+            "json" => { Ok(LogFormat::Json) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFormat::Text => { write!(f, "text") }
+            LogFormat::Json => { write!(f, "json") }
+        }
+    }
+}
+
+/// Sends `tracing` events to stderr. `filter` takes `RUST_LOG` directives such as `info` or
+/// `warn,rust_tron::game=debug,rust_tron::player_controller=trace`.
+///
+/// Turns and game results are logged at `debug` by `rust_tron::game`, searches at `debug` by
+/// `rust_tron::player_controller`, and every move tried on a board at `trace` by
+/// `rust_tron::board`, including those made inside searches.
+///
+/// Fails on a bad filter or if logging was already set up.
+#[allow(clippy::result_unit_err)]
+pub fn init(filter: &str, format: LogFormat) -> Result<(), ()> {
+    let filter = EnvFilter::try_new(filter).map_err(|_| ())?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => { builder.try_init().map_err(|_| ()) }
+        LogFormat::Json => { builder.json().with_current_span(true).with_span_list(true).try_init().map_err(|_| ()) }
+    }
+}
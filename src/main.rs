@@ -1,32 +1,357 @@
-mod board;
-mod player;
-mod position;
-mod power_up;
-mod player_controller;
-mod game;
-
 use std::collections::HashMap;
-use crate::player_controller::{PlayerController, ClockwiseController, BFSController};
-use crate::game::{Game, GameState};
+use std::path::PathBuf;
+use std::process::exit;
+use std::time::Duration;
+use rust_tron::player_controller::BFSController;
+use rust_tron::event::GameEvent;
+use rust_tron::game::{Game, GameState};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tracing::info;
+use rust_tron::bench::{self, BenchConfig};
+use rust_tron::map_generator::{generate, MapConfig};
+use rust_tron::logging::{self, LogFormat};
+use rust_tron::network::Network;
+use rust_tron::rules::Rules;
+use rust_tron::selfplay::{self, SelfPlayConfig};
+use rust_tron::tune::{self, candidate_spec, TuneConfig};
 
 
 fn main() {
-    let mut controller_clockwise = ClockwiseController{};
-    let mut controller_smart = BFSController{ turn_time_milliseconds: 1000, score_tracker: HashMap::new() };
-    let mut controller_smart_2 = BFSController{ turn_time_milliseconds: 1000, score_tracker: HashMap::new() };
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    init_logging(&mut args);
+    match args.first().map(|command| command.as_str()) {
+        None => { demo() }
+        Some("selfplay") => { run_selfplay(parse_flags(&args[1..])) }
+        Some("tune") => { run_tune(parse_flags(&args[1..])) }
+        Some("bench-search") => { bench_search(parse_flags(&args[1..])) }
+        Some("map") => { print_map(parse_flags(&args[1..])) }
+        Some("rules") => { print_rules(parse_flags(&args[1..])) }
+        Some("check-network") => { check_network(parse_flags(&args[1..])) }
+        Some(command) => {
+            eprintln!("unknown command: {}", command);
+            exit(2);
+        }
+    }
+}
+
+/// Sets up logging from `--log <filter>` and `--log-format text|json`, which may appear
+/// anywhere on the command line and are removed from `args`. Without `--log` the filter
+/// comes from `RUST_LOG`, falling back to `info`.
+fn init_logging(args: &mut Vec<String>) {
+    let mut take = |name: &str| {
+        let index = args.iter().position(|arg| arg == name)?;
+        if index + 1 >= args.len() {
+            eprintln!("expected a value after {}", name);
+            exit(2);
+        }
+        let value = args.remove(index + 1);
+        args.remove(index);
+        Some(value)
+    };
+    let filter = take("--log")
+        .or_else(|| std::env::var("RUST_LOG").ok())
+        .unwrap_or_else(|| "info".to_string());
+    let format = match take("--log-format").map(|format| format.parse()) {
+        None => { LogFormat::default() }
+        Some(Ok(format)) => { format }
+        Some(Err(_)) => {
+            eprintln!("invalid value for --log-format, expected text or json");
+            exit(2);
+        }
+    };
+    if logging::init(&filter, format).is_err() {
+        eprintln!("invalid value for --log: {}", filter);
+        exit(2);
+    }
+}
+
+/// Parses `--name value` pairs.
+fn parse_flags(args: &[String]) -> HashMap<String, String> {
+    let mut flags = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.strip_prefix("--"), args.next()) {
+            (Some(name), Some(value)) => { flags.insert(name.to_string(), value.clone()); }
+            _ => {
+                eprintln!("expected `--name value`, got: {}", arg);
+                exit(2);
+            }
+        }
+    }
+    flags
+}
+
+fn flag<T: std::str::FromStr>(flags: &HashMap<String, String>, name: &str, default: T) -> T {
+    match flags.get(name) {
+        None => { default }
+        Some(value) => {
+            match value.parse() {
+                Ok(value) => { value }
+                Err(_) => {
+                    eprintln!("invalid value for --{}: {}", name, value);
+                    exit(2);
+                }
+            }
+        }
+    }
+}
+
+/// Like `flag`, for settings that are off unless given.
+fn optional_flag<T: std::str::FromStr>(flags: &HashMap<String, String>, name: &str) -> Option<T> {
+    match flags.get(name) {
+        None => { None }
+        Some(value) => {
+            match value.parse() {
+                Ok(value) => { Some(value) }
+                Err(_) => {
+                    eprintln!("invalid value for --{}: {}", name, value);
+                    exit(2);
+                }
+            }
+        }
+    }
+}
+
+/// Like `flag`, for rules that can be off: `none` turns `name` off, overriding a preset.
+fn rule_flag<T: std::str::FromStr>(flags: &HashMap<String, String>, name: &str, default: Option<T>) -> Option<T> {
+    match flags.get(name).map(String::as_str) {
+        None => { default }
+        Some("none") => { None }
+        Some(_) => { optional_flag(flags, name) }
+    }
+}
+
+/// Rules from `--rules`, a preset name or a TOML file and `powerups` if not given, with
+/// individual rules overridden by their own flags.
+fn rules_flags(flags: &HashMap<String, String>) -> Rules {
+    let mut rules = match flags.get("rules") {
+        None => { Rules::default() }
+        Some(spec) => {
+            match Rules::resolve(spec) {
+                Ok(rules) => { rules }
+                Err(err) => {
+                    eprintln!("invalid value for --rules: {}: {}", spec, err);
+                    exit(2);
+                }
+            }
+        }
+    };
+    rules.topology = flag(flags, "topology", rules.topology);
+    rules.steering = flag(flags, "steering", rules.steering);
+    rules.wall = flag(flags, "wall", rules.wall);
+    rules.trail = flag(flags, "trail", rules.trail);
+    rules.head_on = flag(flags, "head-on", rules.head_on);
+    rules.trails = flag(flags, "trails", rules.trails);
+    rules.friendly_fire = flag(flags, "friendly-fire", rules.friendly_fire);
+    rules.boost_moves = flag(flags, "boost-moves", rules.boost_moves);
+    rules.max_armor = rule_flag(flags, "max-armor", rules.max_armor);
+    rules.blast = flag(flags, "blast", rules.blast);
+    rules.inventory_slots = flag(flags, "inventory", rules.inventory_slots);
+    rules.sudden_death = rule_flag(flags, "sudden-death", rules.sudden_death);
+    rules.turn_limit = rule_flag(flags, "turn-limit", rules.turn_limit);
+    rules.visibility = flag(flags, "visibility", rules.visibility);
+    rules
+}
+
+/// Prints the rules the other commands would play by with the same flags, as TOML.
+fn print_rules(flags: HashMap<String, String>) {
+    print!("{}", rules_flags(&flags));
+}
+
+fn run_selfplay(flags: HashMap<String, String>) {
+    let defaults = SelfPlayConfig::default();
+    let config = SelfPlayConfig {
+        controllers: match flags.get("controllers") {
+            Some(specs) => { specs.split(',').map(|spec| spec.to_string()).collect() }
+            None => { defaults.controllers }
+        },
+        teams: match flags.get("teams") {
+            Some(teams) => {
+                match teams.split(',').map(|team| team.parse()).collect() {
+                    Ok(teams) => { teams }
+                    Err(_) => {
+                        eprintln!("invalid value for --teams: {}", teams);
+                        exit(2);
+                    }
+                }
+            }
+            None => { defaults.teams }
+        },
+        games: flag(&flags, "games", defaults.games),
+        seed: flag(&flags, "seed", defaults.seed),
+        height: flag(&flags, "height", defaults.height),
+        width: flag(&flags, "width", defaults.width),
+        grid: flag(&flags, "grid", defaults.grid),
+        obstacle_density: flag(&flags, "obstacles", defaults.obstacle_density),
+        symmetry: flag(&flags, "symmetry", defaults.symmetry),
+        rules: rules_flags(&flags),
+        time_control: optional_flag(&flags, "time-control"),
+        overrun_penalty: flag(&flags, "overrun", defaults.overrun_penalty),
+        controller_deadline: optional_flag(&flags, "deadline").map(Duration::from_millis),
+        power_ups: flag(&flags, "power-ups", defaults.power_ups),
+        opening_moves: flag(&flags, "opening-moves", defaults.opening_moves),
+        shard_size: flag(&flags, "shard-size", defaults.shard_size),
+        output: flag(&flags, "output", defaults.output),
+    };
+    match selfplay::run(&config) {
+        Ok(summary) => {
+            println!("{} games, {} positions in {} shards, wins: {:?}, draws: {}",
+                     summary.games, summary.positions, summary.shards, summary.wins, summary.draws);
+        }
+        Err(err) => {
+            eprintln!("selfplay failed: {}", err);
+            exit(1);
+        }
+    }
+}
+
+fn run_tune(flags: HashMap<String, String>) {
+    let defaults = TuneConfig::default();
+    let config = TuneConfig {
+        population: flag(&flags, "population", defaults.population),
+        generations: flag(&flags, "generations", defaults.generations),
+        games: flag(&flags, "games", defaults.games),
+        turn_time_milliseconds: flag(&flags, "turn-time", defaults.turn_time_milliseconds),
+        opponent: flag(&flags, "opponent", defaults.opponent),
+        seed: flag(&flags, "seed", defaults.seed),
+        mutation: flag(&flags, "mutation", defaults.mutation),
+        elite: flag(&flags, "elite", defaults.elite),
+        threads: flag(&flags, "threads", defaults.threads),
+        height: flag(&flags, "height", defaults.height),
+        width: flag(&flags, "width", defaults.width),
+        grid: flag(&flags, "grid", defaults.grid),
+        obstacle_density: flag(&flags, "obstacles", defaults.obstacle_density),
+        symmetry: flag(&flags, "symmetry", defaults.symmetry),
+        rules: rules_flags(&flags),
+        time_control: optional_flag(&flags, "time-control"),
+        overrun_penalty: flag(&flags, "overrun", defaults.overrun_penalty),
+        controller_deadline: optional_flag(&flags, "deadline").map(Duration::from_millis),
+        power_ups: flag(&flags, "power-ups", defaults.power_ups),
+        opening_moves: flag(&flags, "opening-moves", defaults.opening_moves),
+        output: flag(&flags, "output", defaults.output),
+        resume: flags.get("resume").map(PathBuf::from),
+    };
+    match tune::run(&config) {
+        Ok(best) => {
+            println!("best: {} ({:.3})", candidate_spec(config.turn_time_milliseconds, &best.parameters), best.fitness);
+        }
+        Err(err) => {
+            eprintln!("tune failed: {}", err);
+            exit(1);
+        }
+    }
+}
+
+/// Compares search speed and strength across thread counts.
+fn bench_search(flags: HashMap<String, String>) {
+    let defaults = BenchConfig::default();
+    let config = BenchConfig {
+        threads: match flags.get("threads") {
+            Some(threads) => {
+                match threads.split(',').map(|count| count.parse()).collect() {
+                    Ok(threads) => { threads }
+                    Err(_) => {
+                        eprintln!("invalid value for --threads: {}", threads);
+                        exit(2);
+                    }
+                }
+            }
+            None => { defaults.threads }
+        },
+        turn_time_milliseconds: flag(&flags, "turn-time", defaults.turn_time_milliseconds),
+        positions: flag(&flags, "positions", defaults.positions),
+        games: flag(&flags, "games", defaults.games),
+        seed: flag(&flags, "seed", defaults.seed),
+        height: flag(&flags, "height", defaults.height),
+        width: flag(&flags, "width", defaults.width),
+    };
+    match bench::run(&config) {
+        Ok(rows) => {
+            println!("threads  running      nodes  seconds    nodes/s  score vs 1 thread");
+            for row in rows {
+                println!("{:>7} {:>8} {:>10} {:>8.2} {:>10.0} {:>6.3}",
+                         row.threads, row.effective_threads, row.nodes, row.seconds, row.nodes_per_second, row.score);
+            }
+        }
+        Err(_) => {
+            eprintln!("no arena fits these settings");
+            exit(1);
+        }
+    }
+}
+
+/// Prints a generated arena, for checking generator settings by eye.
+fn print_map(flags: HashMap<String, String>) {
+    let defaults = MapConfig::default();
+    let config = MapConfig {
+        height: flag(&flags, "height", defaults.height),
+        width: flag(&flags, "width", defaults.width),
+        grid: flag(&flags, "grid", defaults.grid),
+        obstacle_density: flag(&flags, "obstacles", defaults.obstacle_density),
+        symmetry: flag(&flags, "symmetry", defaults.symmetry),
+        power_up_clusters: flag(&flags, "clusters", defaults.power_up_clusters),
+        cluster_size: flag(&flags, "cluster-size", defaults.cluster_size),
+        rules: rules_flags(&flags),
+        players: flag(&flags, "players", defaults.players),
+    };
+    let mut rng = StdRng::seed_from_u64(flag(&flags, "seed", 0));
+    match generate(&config, &mut rng) {
+        Ok(board) => { print!("{}", board) }
+        Err(_) => {
+            eprintln!("no arena fits these settings");
+            exit(1);
+        }
+    }
+}
+
+/// Verifies a weights file's forward pass against the reference outputs stored in it.
+fn check_network(flags: HashMap<String, String>) {
+    let path: PathBuf = match flags.get("weights") {
+        Some(path) => { PathBuf::from(path) }
+        None => {
+            eprintln!("--weights is required");
+            exit(2);
+        }
+    };
+    let tolerance: f32 = flag(&flags, "tolerance", 1e-4);
+    let network = match Network::load(&path) {
+        Ok(network) => { network }
+        Err(err) => {
+            eprintln!("could not load {}: {}", path.display(), err);
+            exit(1);
+        }
+    };
+    match network.check_references() {
+        Some(max_error) if max_error <= tolerance => {
+            println!("ok: max error {:e}", max_error);
+        }
+        Some(max_error) => {
+            eprintln!("mismatch: max error {:e} exceeds {:e}", max_error, tolerance);
+            exit(1);
+        }
+        None => {
+            eprintln!("{} has no reference outputs", path.display());
+            exit(1);
+        }
+    }
+}
+
+fn demo() {
+    let controller_smart = BFSController::new(1000);
+    let controller_smart_2 = BFSController::new(1000);
     let mut game = Game::new_default([Box::new(controller_smart), Box::new(controller_smart_2)]);
-    loop {
-        match game.state() {
-            GameState::Active { turn, alive_ids: _ } => {
-                println!("{}", turn);
-            }
-            GameState::Over { winner_id: _ } => {
-                break;
-            }
-        };
-        match game.run_turn() {
-            Ok((player_id, actions)) => {
-                println!("{:?}: {:?}", player_id, actions);
+    game.add_observer(Box::new(|event: &GameEvent| info!(?event)));
+    let search_info = game.subscribe_search_info();
+    while let GameState::Active { turn, alive_ids: _ } = game.state() {
+        let turn = *turn;
+        let result = game.run_turn();
+        // Search progress goes to the log, leaving stdout to the board.
+        for info in search_info.try_iter().filter(|info| info.done) {
+            info!(turn, "{}", info);
+        }
+        match result {
+            Ok(_) => {
                 println!("{}", game.board());
             }
             Err(_) => {
@@ -35,4 +360,4 @@ fn main() {
         }
     }
     // run turns until err or state is over
-}
\ No newline at end of file
+}
@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use rand::rngs::StdRng;
+use rand::Rng;
+use crate::board::{Board, CellState};
+use crate::grid::Grid;
+use crate::position::Position;
+use crate::rules::Rules;
+use crate::power_up::random_power_up;
+
+/// How obstacles and power-ups repeat around the center, so both spawns see the same arena.
+/// Symmetries act on rows and columns, see `exact_on` for the grids they hold on.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq)]
+pub enum Symmetry {
+    /// No symmetry; spawns in opposite corners.
+    None,
+    /// Half-turn about the center; spawns in opposite corners.
+    #[default]
+    Point,
+    /// Reflection across the vertical center line; spawns facing each other mid-height.
+    Mirror,
+    /// Quarter-turns about the center, needing a square board of squares; spawns in opposite
+    /// corners.
+    Rotational,
+}
+
+impl Symmetry {
+    /// Whether the symmetry maps a `height` x `width` board of `grid` onto itself, neighbours
+    /// onto neighbours. On hex grids, with odd rows shifted right, only a half-turn works, and
+    /// only when it swaps even and odd rows, i.e. the height is even.
+    pub fn exact_on(&self, grid: Grid, height: usize, width: usize) -> bool {
+        match (grid, self) {
+            (_, Symmetry::None) => { true }
+            (Grid::Square, Symmetry::Rotational) => { height == width }
+            (Grid::Square, _) => { true }
+            (Grid::Hex, Symmetry::Point) => { height.is_multiple_of(2) }
+            (Grid::Hex, _) => { false }
+        }
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => { Ok(Symmetry::None) }
+            "point" => { Ok(Symmetry::Point) }
+            "mirror" => { Ok(Symmetry::Mirror) }
+            "rotational" => { Ok(Symmetry::Rotational) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Symmetry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symmetry::None => { write!(f, "none") }
+            Symmetry::Point => { write!(f, "point") }
+            Symmetry::Mirror => { write!(f, "mirror") }
+            Symmetry::Rotational => { write!(f, "rotational") }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MapConfig {
+    pub height: usize,
+    pub width: usize,
+    pub grid: Grid,
+    /// Fraction of cells to turn into walls, before symmetry.
+    pub obstacle_density: f64,
+    pub symmetry: Symmetry,
+    pub power_up_clusters: usize,
+    /// Power-ups per cluster, placed on connected cells around a random center.
+    pub cluster_size: usize,
+    /// The rules the map is played by, including its topology.
+    pub rules: Rules,
+    /// Between 2 and 4 players, spawning in turn order at the corners or, for `Mirror`, along
+    /// the left and right edges.
+    pub players: usize,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            height: 10,
+            width: 10,
+            grid: Grid::Square,
+            obstacle_density: 0.1,
+            symmetry: Symmetry::Point,
+            power_up_clusters: 2,
+            cluster_size: 2,
+            rules: Rules::default(),
+            players: 2,
+        }
+    }
+}
+
+/// Layouts that leave the spawns disconnected are redrawn this many times before falling back
+/// to an arena without obstacles.
+const MAX_ATTEMPTS: usize = 100;
+
+/// Generates an arena for `config.players` players. The spawns are always connected to each
+/// other, and obstacles and power-ups follow `config.symmetry`. Returns `Err` for a symmetry
+/// that is not exact on the board, see `Symmetry::exact_on`, or a grid that does not fit the
+/// topology.
+#[allow(clippy::result_unit_err)]
+pub fn generate(config: &MapConfig, rng: &mut StdRng) -> Result<Board, ()> {
+    if !config.symmetry.exact_on(config.grid, config.height, config.width) {
+        return Err(())
+    }
+    if !config.grid.fits(config.rules.topology, config.height) {
+        return Err(())
+    }
+    let spawns = spawns(config)?;
+    let mut board = Board::with_spawns(config.height, config.width, &spawns)?;
+    board.set_grid(config.grid)?;
+    board.set_rules(config.rules)?;
+
+    let mut arena = board.clone();
+    for _ in 0..MAX_ATTEMPTS {
+        let mut candidate = board.clone();
+        place_obstacles(&mut candidate, config, &spawns, rng);
+        let reached = reachable(&candidate, spawns[0]);
+        if spawns.iter().all(|spawn| reached.contains(spawn)) {
+            arena = candidate;
+            break
+        }
+    }
+    place_power_ups(&mut arena, config, &spawns, rng);
+    Ok(arena)
+}
+
+fn spawns(config: &MapConfig) -> Result<Vec<Position>, ()> {
+    let (height, width) = (config.height, config.width);
+    if !(2..=4).contains(&config.players) || height * width < 2 {
+        return Err(())
+    }
+    let mut spawns = match (config.symmetry, config.players) {
+        (Symmetry::Mirror, 2) => {
+            vec![Position::new(height / 2, 0), Position::new(height / 2, width - 1)]
+        }
+        (Symmetry::Mirror, _) => {
+            let (top, bottom) = (height / 3, height - 1 - height / 3);
+            vec![
+                Position::new(top, 0),
+                Position::new(top, width - 1),
+                Position::new(bottom, 0),
+                Position::new(bottom, width - 1),
+            ]
+        }
+        _ => {
+            vec![
+                Position::new(0, 0),
+                Position::new(height - 1, width - 1),
+                Position::new(0, width - 1),
+                Position::new(height - 1, 0),
+            ]
+        }
+    };
+    spawns.truncate(config.players);
+    for (index, spawn) in spawns.iter().enumerate() {
+        if spawns[..index].contains(spawn) {
+            return Err(())
+        }
+    }
+    Ok(spawns)
+}
+
+/// `position` and its images under `symmetry`, without duplicates.
+pub fn orbit(position: Position, symmetry: Symmetry, height: usize, width: usize) -> Vec<Position> {
+    let (row, column) = (position.row, position.column);
+    let images = match symmetry {
+        Symmetry::None => { vec![position] }
+        Symmetry::Point => { vec![position, Position::new(height - 1 - row, width - 1 - column)] }
+        Symmetry::Mirror => { vec![position, Position::new(row, width - 1 - column)] }
+        Symmetry::Rotational => {
+            let n = height - 1;
+            vec![
+                position,
+                Position::new(column, n - row),
+                Position::new(n - row, n - column),
+                Position::new(n - column, row),
+            ]
+        }
+    };
+    let mut orbit = Vec::new();
+    for image in images {
+        if !orbit.contains(&image) {
+            orbit.push(image);
+        }
+    }
+    orbit
+}
+
+fn place_obstacles(board: &mut Board, config: &MapConfig, spawns: &[Position], rng: &mut StdRng) {
+    let mut visited = vec![vec![false; config.width]; config.height];
+    for row in 0..config.height {
+        for column in 0..config.width {
+            if visited[row][column] {
+                continue
+            }
+            let orbit = orbit(Position::new(row, column), config.symmetry, config.height, config.width);
+            for position in &orbit {
+                visited[position.row][position.column] = true;
+            }
+            // Keep the cells next to each spawn open so nobody starts boxed in.
+            let near_spawn = orbit.iter().any(|position| {
+                spawns.iter().any(|spawn| spawn == position || board.neighbors(*spawn).contains(position))
+            });
+            if !near_spawn && rng.gen_bool(config.obstacle_density.clamp(0.0, 1.0)) {
+                for position in orbit {
+                    let _ = board.place_wall(position);
+                }
+            }
+        }
+    }
+}
+
+fn place_power_ups(board: &mut Board, config: &MapConfig, spawns: &[Position], rng: &mut StdRng) {
+    let open: Vec<Position> = reachable(board, spawns[0]);
+    for _ in 0..config.power_up_clusters {
+        // Only cells whose every image is still empty, so the power-ups land symmetrically.
+        let free: Vec<Position> = open.iter().copied()
+            .filter(|position| {
+                orbit(*position, config.symmetry, config.height, config.width).iter()
+                    .all(|image| board.get_cell(*image).map(|cell| cell.get_state()) == Ok(CellState::Empty))
+            })
+            .collect();
+        let power_up = random_power_up(rng);
+        if free.is_empty() {
+            return
+        }
+        let mut cluster = vec![free[rng.gen_range(0..free.len())]];
+        while cluster.len() < config.cluster_size {
+            let frontier: Vec<Position> = cluster.iter()
+                .flat_map(|position| board.neighbors(*position))
+                .filter(|position| !cluster.contains(position) && free.contains(position))
+                .collect();
+            if frontier.is_empty() {
+                break
+            }
+            cluster.push(frontier[rng.gen_range(0..frontier.len())]);
+        }
+        for position in cluster {
+            for image in orbit(position, config.symmetry, config.height, config.width) {
+                let _ = board.place_power_up(image, power_up);
+            }
+        }
+    }
+}
+
+/// Cells reachable from `start` without crossing walls or trails.
+fn reachable(board: &Board, start: Position) -> Vec<Position> {
+    let mut seen = vec![vec![false; board.width()]; board.height()];
+    let mut reached = Vec::new();
+    let mut queue = VecDeque::from([start]);
+    seen[start.row][start.column] = true;
+    while let Some(position) = queue.pop_front() {
+        reached.push(position);
+        for neighbor in board.neighbors(position) {
+            if seen[neighbor.row][neighbor.column] {
+                continue
+            }
+            match board.get_cell(neighbor).map(|cell| cell.get_state()) {
+                Ok(CellState::Wall) | Ok(CellState::Owned { .. }) | Err(_) => { continue }
+                _ => {}
+            }
+            seen[neighbor.row][neighbor.column] = true;
+            queue.push_back(neighbor);
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::player::PlayerState;
+
+    const SYMMETRIES: [Symmetry; 4] = [Symmetry::None, Symmetry::Point, Symmetry::Mirror, Symmetry::Rotational];
+
+    /// Every board shape and symmetry `generate` accepts, with 2 and 4 players.
+    fn configs() -> Vec<MapConfig> {
+        let mut configs = Vec::new();
+        for grid in [Grid::Square, Grid::Hex] {
+            for (height, width) in [(10, 10), (8, 12), (9, 9)] {
+                for symmetry in SYMMETRIES {
+                    for players in [2, 4] {
+                        if symmetry.exact_on(grid, height, width) {
+                            configs.push(MapConfig { height, width, grid, symmetry, players, obstacle_density: 0.3, ..MapConfig::default() });
+                        }
+                    }
+                }
+            }
+        }
+        configs
+    }
+
+    /// A cell's state in the arena, with spawns taken as empty: fewer players than images
+    /// leave some images of a spawn free.
+    fn kind(board: &Board, position: Position) -> CellState {
+        match board.get_cell(position).unwrap().get_state() {
+            CellState::Occupied { .. } => { CellState::Empty }
+            state => { state }
+        }
+    }
+
+    #[test]
+    fn maps_follow_their_symmetry() {
+        for config in configs() {
+            for seed in 0..10 {
+                let board = generate(&config, &mut StdRng::seed_from_u64(seed)).unwrap();
+                for row in 0..config.height {
+                    for column in 0..config.width {
+                        let position = Position::new(row, column);
+                        for image in orbit(position, config.symmetry, config.height, config.width) {
+                            assert_eq!(kind(&board, image), kind(&board, position), "{:?} seed {}", config, seed);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symmetries_map_neighbours_onto_neighbours() {
+        for config in configs() {
+            let board = generate(&config, &mut StdRng::seed_from_u64(0)).unwrap();
+            for row in 0..config.height {
+                for column in 0..config.width {
+                    let position = Position::new(row, column);
+                    let images = orbit(position, config.symmetry, config.height, config.width);
+                    for neighbor in board.neighbors(position) {
+                        let neighbor_images = orbit(neighbor, config.symmetry, config.height, config.width);
+                        for (image, neighbor_image) in images.iter().zip(&neighbor_images) {
+                            assert!(board.neighbors(*image).contains(neighbor_image), "{:?}", config);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_can_reach_each_other() {
+        for mut config in configs() {
+            config.obstacle_density = 0.5;
+            for seed in 0..5 {
+                let board = generate(&config, &mut StdRng::seed_from_u64(seed)).unwrap();
+                let spawns: Vec<Position> = board.players().iter()
+                    .map(|player| match player.get_state() {
+                        PlayerState::Alive { position, .. } => { position }
+                        PlayerState::Dead => { panic!("players start alive") }
+                    })
+                    .collect();
+                let reached = reachable(&board, spawns[0]);
+                assert!(spawns.iter().all(|spawn| reached.contains(spawn)), "{:?} seed {}", config, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_draws_the_same_map() {
+        for config in configs() {
+            let first = generate(&config, &mut StdRng::seed_from_u64(7)).unwrap();
+            assert_eq!(first, generate(&config, &mut StdRng::seed_from_u64(7)).unwrap());
+        }
+    }
+
+    #[test]
+    fn hex_grids_refuse_inexact_symmetries() {
+        let config = |symmetry, height| MapConfig { grid: Grid::Hex, height, width: 10, symmetry, ..MapConfig::default() };
+        assert!(generate(&config(Symmetry::Point, 9), &mut StdRng::seed_from_u64(0)).is_err());
+        assert!(generate(&config(Symmetry::Mirror, 10), &mut StdRng::seed_from_u64(0)).is_err());
+        assert!(generate(&config(Symmetry::Rotational, 10), &mut StdRng::seed_from_u64(0)).is_err());
+        assert!(generate(&config(Symmetry::Point, 10), &mut StdRng::seed_from_u64(0)).is_ok());
+    }
+}
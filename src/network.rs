@@ -0,0 +1,247 @@
+//! A small feed-forward network evaluated on the CPU.
+//!
+//! Weights files are whitespace-separated tokens, so they are easy to write from Python:
+//!
+//! ```text
+//! tron-net 1
+//! input <planes> <height> <width>
+//! conv <in_channels> <out_channels> <kernel> <weights: out*in*kernel*kernel> <biases: out>
+//! dense <inputs> <outputs> <weights: outputs*inputs> <biases: outputs>
+//! relu
+//! tanh
+//! reference <inputs...> <outputs...>
+//! ```
+//!
+//! Convolutions use odd kernels with zero "same" padding; `dense` flattens its input. Weights
+//! are row-major, matching PyTorch's `Conv2d.weight` and `Linear.weight`. The output is
+//! `[value, policy logit per action in Board::actions order]`. `reference` lines record inputs
+//! and the outputs a reference implementation produced for them, checked by
+//! `check_references`.
+
+use std::io;
+use std::path::Path;
+use crate::observation::Observation;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Layer {
+    Conv {
+        in_channels: usize,
+        out_channels: usize,
+        kernel: usize,
+        weights: Vec<f32>,
+        biases: Vec<f32>,
+    },
+    Dense {
+        inputs: usize,
+        outputs: usize,
+        weights: Vec<f32>,
+        biases: Vec<f32>,
+    },
+    Relu,
+    Tanh,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    /// (planes, height, width) of the input.
+    input_shape: (usize, usize, usize),
+    layers: Vec<Layer>,
+    references: Vec<(Vec<f32>, Vec<f32>)>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Tokens<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        let token = self.next().ok_or_else(|| invalid("unexpected end of file".to_string()))?;
+        token.parse().map_err(|_| invalid(format!("expected an integer, got {}", token)))
+    }
+
+    fn floats(&mut self, count: usize) -> io::Result<Vec<f32>> {
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            let token = self.next().ok_or_else(|| invalid("unexpected end of file".to_string()))?;
+            values.push(token.parse().map_err(|_| invalid(format!("expected a number, got {}", token)))?);
+        }
+        Ok(values)
+    }
+}
+
+#[allow(clippy::result_unit_err)]
+impl Network {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Network::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut tokens = Tokens { tokens: text.split_whitespace() };
+        if tokens.next() != Some("tron-net") || tokens.usize()? != 1 {
+            return Err(invalid("not a version 1 tron-net file".to_string()))
+        }
+        if tokens.next() != Some("input") {
+            return Err(invalid("expected the input shape first".to_string()))
+        }
+        let input_shape = (tokens.usize()?, tokens.usize()?, tokens.usize()?);
+
+        let mut layers = Vec::new();
+        let mut references = Vec::new();
+        // Track the running shape so malformed files fail here rather than mid-game.
+        let (mut channels, height, width) = input_shape;
+        let mut flat = false;
+        let mut size = channels * height * width;
+        while let Some(token) = tokens.next() {
+            match token {
+                "conv" => {
+                    let (in_channels, out_channels, kernel) = (tokens.usize()?, tokens.usize()?, tokens.usize()?);
+                    if flat || in_channels != channels || kernel % 2 == 0 {
+                        return Err(invalid(format!("conv {} {} {} does not fit its input", in_channels, out_channels, kernel)))
+                    }
+                    let weights = tokens.floats(out_channels * in_channels * kernel * kernel)?;
+                    let biases = tokens.floats(out_channels)?;
+                    layers.push(Layer::Conv { in_channels, out_channels, kernel, weights, biases });
+                    channels = out_channels;
+                    size = channels * height * width;
+                }
+                "dense" => {
+                    let (inputs, outputs) = (tokens.usize()?, tokens.usize()?);
+                    if inputs != size {
+                        return Err(invalid(format!("dense {} {} expects {} inputs", inputs, outputs, size)))
+                    }
+                    let weights = tokens.floats(outputs * inputs)?;
+                    let biases = tokens.floats(outputs)?;
+                    layers.push(Layer::Dense { inputs, outputs, weights, biases });
+                    flat = true;
+                    size = outputs;
+                }
+                "relu" => { layers.push(Layer::Relu) }
+                "tanh" => { layers.push(Layer::Tanh) }
+                "reference" => {
+                    let input = tokens.floats(input_shape.0 * input_shape.1 * input_shape.2)?;
+                    let output = tokens.floats(size)?;
+                    references.push((input, output));
+                }
+                _ => { return Err(invalid(format!("unknown layer: {}", token))) }
+            }
+        }
+        Ok(Network { input_shape, layers, references })
+    }
+
+    pub fn input_shape(&self) -> (usize, usize, usize) {
+        self.input_shape
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Result<Vec<f32>, ()> {
+        let (planes, height, width) = self.input_shape;
+        if input.len() != planes * height * width {
+            return Err(())
+        }
+        let mut values = input.to_vec();
+        for layer in &self.layers {
+            values = match layer {
+                Layer::Conv { in_channels, out_channels, kernel, weights, biases } => {
+                    conv(&values, *in_channels, *out_channels, *kernel, height, width, weights, biases)
+                }
+                Layer::Dense { inputs, outputs, weights, biases } => {
+                    let mut out = biases.clone();
+                    for (o, value) in out.iter_mut().enumerate().take(*outputs) {
+                        let row = &weights[o * inputs..(o + 1) * inputs];
+                        *value += row.iter().zip(&values).map(|(w, x)| w * x).sum::<f32>();
+                    }
+                    out
+                }
+                Layer::Relu => { values.iter().map(|x| x.max(0.0)).collect() }
+                Layer::Tanh => { values.iter().map(|x| x.tanh()).collect() }
+            };
+        }
+        Ok(values)
+    }
+
+    pub fn evaluate(&self, observation: &Observation) -> Result<Vec<f32>, ()> {
+        if (observation.planes, observation.height, observation.width) != self.input_shape {
+            return Err(())
+        }
+        self.forward(&observation.data)
+    }
+
+    /// Runs every `reference` input and returns the largest absolute difference from its
+    /// recorded output, or `None` if the file has no references.
+    pub fn check_references(&self) -> Option<f32> {
+        let mut max_error: Option<f32> = None;
+        for (input, expected) in &self.references {
+            let output = self.forward(input).ok()?;
+            for (a, b) in output.iter().zip(expected) {
+                max_error = Some(max_error.unwrap_or(0.0).max((a - b).abs()));
+            }
+        }
+        max_error
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn conv(input: &[f32], in_channels: usize, out_channels: usize, kernel: usize,
+        height: usize, width: usize, weights: &[f32], biases: &[f32]) -> Vec<f32> {
+    let half = (kernel / 2) as isize;
+    let mut out = vec![0.0; out_channels * height * width];
+    for o in 0..out_channels {
+        for row in 0..height {
+            for column in 0..width {
+                let mut sum = biases[o];
+                for i in 0..in_channels {
+                    for k_row in 0..kernel {
+                        let in_row = row as isize + k_row as isize - half;
+                        if in_row < 0 || in_row >= height as isize {
+                            continue
+                        }
+                        for k_column in 0..kernel {
+                            let in_column = column as isize + k_column as isize - half;
+                            if in_column < 0 || in_column >= width as isize {
+                                continue
+                            }
+                            let w = weights[((o * in_channels + i) * kernel + k_row) * kernel + k_column];
+                            sum += w * input[(i * height + in_row as usize) * width + in_column as usize];
+                        }
+                    }
+                }
+                out[(o * height + row) * width + column] = sum;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TINY: &str = include_str!("../tests/fixtures/tiny.net");
+
+    #[test]
+    fn forward_pass_matches_reference_outputs() {
+        let network = Network::parse(TINY).unwrap();
+        assert_eq!(network.input_shape(), (2, 3, 3));
+        let max_error = network.check_references().unwrap();
+        assert!(max_error <= 1e-5, "max error {}", max_error);
+    }
+
+    #[test]
+    fn check_references_reports_mismatches() {
+        let network = Network::parse(&TINY.replace("0.274351", "0.3")).unwrap();
+        assert!(network.check_references().unwrap() > 0.02);
+    }
+
+    #[test]
+    fn rejects_layers_that_do_not_fit() {
+        assert!(Network::parse("tron-net 1 input 1 2 2 dense 3 1 0 0 0 0").is_err());
+        assert!(Network::parse("tron-net 1 input 1 2 2 conv 1 1 2 0 0 0 0 0").is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// An element type that can be stored in a `.npy` array.
+pub trait NpyElement: Copy {
+    const DESCR: &'static str;
+
+    fn write_le(&self, out: &mut Vec<u8>);
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for i32 {
+    const DESCR: &'static str = "<i4";
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for u8 {
+    const DESCR: &'static str = "|u1";
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl NpyElement for i8 {
+    const DESCR: &'static str = "|i1";
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Writes `data` as a C-ordered array of `shape` in NumPy's `.npy` format (version 1.0), so it
+/// can be read with `numpy.load`.
+pub fn write_npy<T: NpyElement>(path: &Path, shape: &[usize], data: &[T]) -> std::io::Result<()> {
+    let shape_string = match shape {
+        [length] => { format!("({},)", length) }
+        _ => { format!("({})", shape.iter().map(|dim| dim.to_string()).collect::<Vec<_>>().join(", ")) }
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", T::DESCR, shape_string);
+    // Magic, version and header length take 10 bytes; the header is padded so data starts on a
+    // 64 byte boundary.
+    let padding = 64 - (10 + header.len() + 1) % 64;
+    header.push_str(&" ".repeat(padding % 64));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(10 + header.len() + data.len() * 4);
+    out.extend_from_slice(b"\x93NUMPY\x01\x00");
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for value in data {
+        value.write_le(&mut out);
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&out)?;
+    writer.flush()
+}
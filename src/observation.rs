@@ -0,0 +1,230 @@
+use crate::board::{Board, CellState};
+use crate::player::{Player, PlayerState};
+use crate::position::Position;
+use crate::power_up::{PowerUp, N_POWER_UPS};
+use crate::visibility::PlayerView;
+
+pub const OWN_HEAD: usize = 0;
+pub const OWN_TRAIL: usize = 1;
+pub const OPPONENT_HEADS: usize = 2;
+pub const OPPONENT_TRAILS: usize = 3;
+pub const WALLS: usize = 4;
+pub const DOUBLE_SPEED: usize = 5;
+pub const ARMOR: usize = 6;
+pub const BOMB: usize = 7;
+pub const OWN_BOOST: usize = 8;
+pub const OWN_ARMOR: usize = 9;
+pub const OPPONENT_BOOST: usize = 10;
+pub const OPPONENT_ARMOR: usize = 11;
+pub const PHASE: usize = 12;
+pub const ERASER: usize = 13;
+pub const FREEZE: usize = 14;
+pub const TELEPORT: usize = 15;
+pub const OWN_PHASE: usize = 16;
+pub const OWN_FROZEN: usize = 17;
+pub const OPPONENT_PHASE: usize = 18;
+pub const OPPONENT_FROZEN: usize = 19;
+pub const ALLY_HEADS: usize = 20;
+pub const ALLY_TRAILS: usize = 21;
+/// `N_POWER_UPS` planes, one per `PowerUp::kind`, filled with how many of that kind the
+/// player holds.
+pub const OWN_HELD: usize = 22;
+/// As `OWN_HELD`, with the most any opponent holds.
+pub const OPPONENT_HELD: usize = OWN_HELD + N_POWER_UPS;
+/// `N_POWER_UPS` planes, one per `PowerUp::kind`, with 1 in the kind `Action::Use` would fire
+/// next: the oldest power-up the player holds.
+pub const OWN_NEXT_USE: usize = OPPONENT_HELD + N_POWER_UPS;
+/// 1 on cells the player has seen, so cells out of view can be told from empty ones. All 1
+/// unless encoded with `encode_view`.
+pub const KNOWN: usize = OWN_NEXT_USE + N_POWER_UPS;
+pub const N_PLANES: usize = KNOWN + 1;
+
+/// A board seen from one player's perspective, stored as `N_PLANES` planes of
+/// `height * width` values in row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub planes: usize,
+    pub height: usize,
+    pub width: usize,
+    pub data: Vec<f32>,
+}
+
+impl Observation {
+    fn zeros(planes: usize, height: usize, width: usize) -> Self {
+        Observation { planes, height, width, data: vec![0.0; planes * height * width] }
+    }
+
+    fn index(&self, plane: usize, position: Position) -> usize {
+        (plane * self.height + position.row) * self.width + position.column
+    }
+
+    pub fn get(&self, plane: usize, position: Position) -> f32 {
+        self.data[self.index(plane, position)]
+    }
+
+    fn set(&mut self, plane: usize, position: Position, value: f32) {
+        let index = self.index(plane, position);
+        self.data[index] = value;
+    }
+
+    fn fill(&mut self, plane: usize, value: f32) {
+        let start = plane * self.height * self.width;
+        for v in &mut self.data[start..start + self.height * self.width] {
+            *v = value;
+        }
+    }
+}
+
+pub fn power_up_plane(power_up: &PowerUp) -> usize {
+    match power_up {
+        PowerUp::DoubleSpeed { .. } => { DOUBLE_SPEED }
+        PowerUp::Armor => { ARMOR }
+        PowerUp::Bomb => { BOMB }
+        PowerUp::Phase { .. } => { PHASE }
+        PowerUp::Eraser { .. } => { ERASER }
+        PowerUp::Freeze => { FREEZE }
+        PowerUp::Teleport => { TELEPORT }
+    }
+}
+
+/// How many power-ups of each `PowerUp::kind` `player` holds.
+fn held_counts(player: &Player) -> [usize; N_POWER_UPS] {
+    let mut counts = [0; N_POWER_UPS];
+    for power_up in player.inventory().items() {
+        counts[power_up.kind()] += 1;
+    }
+    counts
+}
+
+/// Encodes `board` as seen by `player_id`. Opponent planes combine every player on another
+/// team, and ally planes the player's teammates. Panics if `player_id` is not on the board.
+pub fn encode(board: &Board, player_id: usize) -> Observation {
+    assert!(player_id < board.players().len(), "no player {} on the board", player_id);
+    let mut observation = Observation::zeros(N_PLANES, board.height(), board.width());
+    for row in 0..board.height() {
+        for column in 0..board.width() {
+            let position = Position::new(row, column);
+            let state = match board.get_cell(position) {
+                Ok(cell) => { cell.get_state() }
+                Err(_) => { continue }
+            };
+            let plane = match state {
+                CellState::Empty => { continue }
+                CellState::PowerUp { power_up } => { power_up_plane(&power_up) }
+                CellState::Wall => { WALLS }
+                CellState::Owned { player_id: owner } => {
+                    if owner == player_id {
+                        OWN_TRAIL
+                    } else if board.allies(player_id, owner) {
+                        ALLY_TRAILS
+                    } else {
+                        OPPONENT_TRAILS
+                    }
+                }
+                CellState::Occupied { player_id: owner } => {
+                    if owner == player_id {
+                        OWN_HEAD
+                    } else if board.allies(player_id, owner) {
+                        ALLY_HEADS
+                    } else {
+                        OPPONENT_HEADS
+                    }
+                }
+            };
+            observation.set(plane, position, 1.0);
+        }
+    }
+
+    let mut opponent_boost = 0;
+    let mut opponent_armor = 0;
+    let mut opponent_phase = 0;
+    let mut opponent_frozen = 0;
+    let mut opponent_held = [0; N_POWER_UPS];
+    for player in board.players() {
+        if let PlayerState::Alive { boost, armor, phase, frozen, .. } = player.get_state() {
+            let held = held_counts(player);
+            if player.get_id() == player_id {
+                observation.fill(OWN_BOOST, boost as f32);
+                observation.fill(OWN_ARMOR, armor as f32);
+                observation.fill(OWN_PHASE, phase as f32);
+                observation.fill(OWN_FROZEN, frozen as f32);
+                for (kind, count) in held.iter().enumerate() {
+                    observation.fill(OWN_HELD + kind, *count as f32);
+                }
+                if let Some(next) = player.inventory().items().next() {
+                    observation.fill(OWN_NEXT_USE + next.kind(), 1.0);
+                }
+            } else if !board.allies(player_id, player.get_id()) {
+                opponent_boost = opponent_boost.max(boost);
+                opponent_armor = opponent_armor.max(armor);
+                opponent_phase = opponent_phase.max(phase);
+                opponent_frozen = opponent_frozen.max(frozen);
+                for (most, count) in opponent_held.iter_mut().zip(held) {
+                    *most = (*most).max(count);
+                }
+            }
+        }
+    }
+    observation.fill(OPPONENT_BOOST, opponent_boost as f32);
+    observation.fill(OPPONENT_ARMOR, opponent_armor as f32);
+    observation.fill(OPPONENT_PHASE, opponent_phase as f32);
+    observation.fill(OPPONENT_FROZEN, opponent_frozen as f32);
+    for (kind, count) in opponent_held.iter().enumerate() {
+        observation.fill(OPPONENT_HELD + kind, *count as f32);
+    }
+    observation.fill(KNOWN, 1.0);
+
+    observation
+}
+
+/// Encodes the board as `view` knows it, with cells never seen taken to be empty and left out
+/// of the `KNOWN` plane.
+pub fn encode_view(view: &PlayerView) -> Observation {
+    let mut observation = encode(view.board(), view.player_id());
+    for row in 0..observation.height {
+        for column in 0..observation.width {
+            let position = Position::new(row, column);
+            if !view.is_known(position) {
+                observation.set(KNOWN, position, 0.0);
+            }
+        }
+    }
+    observation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::Action;
+    use crate::visibility::Visibility;
+
+    #[test]
+    fn next_use_planes_mark_the_oldest_held_power_up() {
+        let mut board = Board::new_default(6, 6).unwrap();
+        board.set_inventory_slots(2);
+        board.place_power_up(Position::new(0, 1), PowerUp::Teleport).unwrap();
+        board.place_power_up(Position::new(0, 2), PowerUp::Freeze).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+
+        let observation = encode(&board, 0);
+        let corner = Position::new(5, 5);
+        assert_eq!(observation.get(OWN_HELD + PowerUp::Teleport.kind(), corner), 1.0);
+        assert_eq!(observation.get(OWN_HELD + PowerUp::Freeze.kind(), corner), 1.0);
+        assert_eq!(observation.get(OWN_NEXT_USE + PowerUp::Teleport.kind(), corner), 1.0);
+        assert_eq!(observation.get(OWN_NEXT_USE + PowerUp::Freeze.kind(), corner), 0.0);
+        assert!((0..N_POWER_UPS).all(|kind| encode(&board, 1).get(OWN_NEXT_USE + kind, corner) == 0.0));
+    }
+
+    #[test]
+    fn the_known_plane_marks_cells_seen() {
+        let mut board = Board::new_default(6, 6).unwrap();
+        assert_eq!(encode(&board, 0).get(KNOWN, Position::new(5, 5)), 1.0);
+        let mut rules = board.rules();
+        rules.visibility = Visibility::Radius { radius: 1 };
+        board.set_rules(rules).unwrap();
+        let observation = encode_view(&PlayerView::new(&board, 0));
+        assert_eq!(observation.get(KNOWN, Position::new(1, 1)), 1.0);
+        assert_eq!(observation.get(KNOWN, Position::new(3, 3)), 0.0);
+    }
+}
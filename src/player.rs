@@ -1,14 +1,28 @@
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::board::Board;
 use crate::position::Position;
+use crate::power_up::Inventory;
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "python", pyo3::pyclass(eq, eq_int, module = "rust_tron"))]
 pub enum Action {
     Up,
     Down,
     Left,
-    Right
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+    /// Uses the oldest held power-up, then carries on straight ahead. Only legal with an
+    /// inventory, see `Board::set_inventory_slots`, and after the first move. Items are used
+    /// strictly in the order they were picked up: there is no choosing a slot, which keeps the
+    /// action space fixed, so a newer item is only reached by using up the ones before it.
+    Use
 }
 
+/// Moves on a square grid.
 pub const ACTIONS: [Action; 4] = [
     Action::Up,
     Action::Right,
@@ -16,50 +30,160 @@ pub const ACTIONS: [Action; 4] = [
     Action::Left
 ];
 
+/// Moves on a hex grid, clockwise from the upper right.
+pub const HEX_ACTIONS: [Action; 6] = [
+    Action::UpRight,
+    Action::Right,
+    Action::DownRight,
+    Action::DownLeft,
+    Action::Left,
+    Action::UpLeft
+];
+
+/// `ACTIONS` and `Action::Use`, for boards with an inventory.
+pub const ACTIONS_WITH_USE: [Action; 5] = [
+    Action::Up,
+    Action::Right,
+    Action::Down,
+    Action::Left,
+    Action::Use
+];
+
+/// `HEX_ACTIONS` and `Action::Use`, for boards with an inventory.
+pub const HEX_ACTIONS_WITH_USE: [Action; 7] = [
+    Action::UpRight,
+    Action::Right,
+    Action::DownRight,
+    Action::DownLeft,
+    Action::Left,
+    Action::UpLeft,
+    Action::Use
+];
+
+#[allow(clippy::result_unit_err)]
 impl Action {
-    pub fn offset_position(&self, position: &Position) -> Result<Position, ()>{
+    /// The (row, column) step this action takes on a square grid.
+    pub fn offset(&self) -> (isize, isize) {
         match self {
-            Action::Up => {
-                position.offset(-1, 0)
-            }
-            Action::Down => {
-                position.offset(1, 0)
-            }
-            Action::Left => {
-                position.offset(0, -1)
-            }
-            Action::Right => {
-                position.offset(0, 1)
-            }
+            Action::Up => { (-1, 0) }
+            Action::Down => { (1, 0) }
+            Action::Left => { (0, -1) }
+            Action::Right => { (0, 1) }
+            Action::UpLeft => { (-1, -1) }
+            Action::UpRight => { (-1, 1) }
+            Action::DownLeft => { (1, -1) }
+            Action::DownRight => { (1, 1) }
+            Action::Use => { (0, 0) }
         }
     }
+
+    /// Where this action leads from `position` on `board`, following its grid and topology.
+    pub fn offset_position(&self, position: &Position, board: &Board) -> Result<Position, ()>{
+        board.step(*position, *self)
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Ord, Eq)]
+/// A move relative to the player's heading.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "python", pyo3::pyclass(eq, eq_int, module = "rust_tron"))]
+pub enum RelativeAction {
+    Left,
+    Straight,
+    Right
+}
+
+pub const RELATIVE_ACTIONS: [RelativeAction; 3] = [
+    RelativeAction::Left,
+    RelativeAction::Straight,
+    RelativeAction::Right
+];
+
+#[allow(clippy::result_unit_err)]
+impl RelativeAction {
+    /// The absolute move this makes when heading along `heading`, turning one step through
+    /// `actions`, which must be in clockwise order.
+    pub fn resolve(&self, heading: Action, actions: &[Action]) -> Result<Action, ()> {
+        let index = actions.iter().position(|action| *action == heading).ok_or(())?;
+        let n_actions = actions.len();
+        Ok(match self {
+            RelativeAction::Left => { actions[(index + n_actions - 1) % n_actions] }
+            RelativeAction::Straight => { actions[index] }
+            RelativeAction::Right => { actions[(index + 1) % n_actions] }
+        })
+    }
+}
+
+/// Which moves are allowed each turn.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Steering {
+    /// Any direction, including back into your own trail.
+    #[default]
+    Absolute,
+    /// Classic light-cycle rules: only left, straight or right of the current heading.
+    Relative,
+}
+
+impl FromStr for Steering {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => { Ok(Steering::Absolute) }
+            "relative" => { Ok(Steering::Relative) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Steering {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Steering::Absolute => { write!(f, "absolute") }
+            Steering::Relative => { write!(f, "relative") }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash)]
 pub enum PlayerState {
     Alive {
         position: Position,
         boost: usize,
-        armor: usize
+        armor: usize,
+        /// The direction of the last move, `None` before the first.
+        heading: Option<Action>,
+        /// Own turns left passing through trails unharmed.
+        phase: usize,
+        /// Turns the player will skip.
+        frozen: usize
     },
     Dead
 }
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Player {
     id: usize,
-    state: PlayerState
+    /// Players sharing a team win together, see `Board::set_teams`.
+    team: usize,
+    state: PlayerState,
+    inventory: Inventory
 }
 
+#[allow(clippy::result_unit_err)]
 impl Player {
     pub fn new(id: usize, position: Position) -> Self {
         Player {
             id,
+            team: id,
             state: PlayerState::Alive {
                 position,
                 boost: 0,
-                armor: 0
-            }
+                armor: 0,
+                heading: None,
+                phase: 0,
+                frozen: 0
+            },
+            inventory: Inventory::default()
         }
     }
 
@@ -71,24 +195,102 @@ impl Player {
         self.state
     }
 
+    pub fn team(&self) -> usize {
+        self.team
+    }
+
+    pub fn set_team(&mut self, team: usize) {
+        self.team = team;
+    }
+
     pub fn set_state(&mut self, state: PlayerState) {
         self.state = state;
     }
 
+    pub fn inventory(&self) -> Inventory {
+        self.inventory
+    }
+
+    pub fn set_inventory(&mut self, inventory: Inventory) {
+        self.inventory = inventory;
+    }
+
     pub fn speed_boost(&mut self, duration: usize) -> Result<(), ()> {
-        match self.get_state() {
-            PlayerState::Alive { position, boost, armor } => {
-                self.set_state(PlayerState::Alive {position, boost: boost + duration, armor});
+        match &mut self.state {
+            PlayerState::Alive { boost, .. } => {
+                *boost += duration;
+                Ok(())
+            }
+            PlayerState::Dead => { Err(()) }
+        }
+    }
+
+    /// Adds a point of armor, up to `max_armor` if there is a cap.
+    pub fn armor_up(&mut self, max_armor: Option<usize>) -> Result<(), ()> {
+        match &mut self.state {
+            PlayerState::Alive { armor, .. } => {
+                *armor = (*armor + 1).min(max_armor.unwrap_or(usize::MAX));
+                Ok(())
+            }
+            PlayerState::Dead => { Err(()) }
+        }
+    }
+
+    /// Lets the player pass through trails for `duration` more of their turns.
+    pub fn phase_out(&mut self, duration: usize) -> Result<(), ()> {
+        match &mut self.state {
+            PlayerState::Alive { phase, .. } => {
+                *phase += duration;
                 Ok(())
             }
             PlayerState::Dead => { Err(()) }
         }
     }
 
-    pub fn armor_up(&mut self) -> Result<(), ()> {
-        match self.get_state() {
-            PlayerState::Alive { position, boost, armor } => {
-                self.set_state(PlayerState::Alive {position, boost, armor: armor + 1});
+    /// Makes the player skip their next turn.
+    pub fn freeze(&mut self) -> Result<(), ()> {
+        match &mut self.state {
+            PlayerState::Alive { frozen, .. } => {
+                *frozen += 1;
+                Ok(())
+            }
+            PlayerState::Dead => { Err(()) }
+        }
+    }
+
+    /// Counts down phase at the end of the player's turn.
+    pub fn wear_off_phase(&mut self) {
+        if let PlayerState::Alive { phase, .. } = &mut self.state {
+            *phase = phase.saturating_sub(1);
+        }
+    }
+
+    /// Uses up one turn the player has to skip, returning whether they had one.
+    pub fn thaw(&mut self) -> bool {
+        match &mut self.state {
+            PlayerState::Alive { frozen, .. } if *frozen > 0 => {
+                *frozen -= 1;
+                true
+            }
+            _ => { false }
+        }
+    }
+
+    /// Moves the player's head to `position` without leaving a trail.
+    pub fn relocate(&mut self, position: Position) -> Result<(), ()> {
+        match &mut self.state {
+            PlayerState::Alive { position: current, .. } => {
+                *current = position;
+                Ok(())
+            }
+            PlayerState::Dead => { Err(()) }
+        }
+    }
+
+    pub fn set_heading(&mut self, heading: Action) -> Result<(), ()> {
+        match &mut self.state {
+            PlayerState::Alive { heading: current, .. } => {
+                *current = Some(heading);
                 Ok(())
             }
             PlayerState::Dead => { Err(()) }
@@ -96,15 +298,13 @@ impl Player {
     }
 
     pub fn take_damage(&mut self) -> Result<(), ()> {
-        match self.get_state() {
-            PlayerState::Alive { position, boost, armor } => {
-                let new_state = if armor > 0 {
-                    PlayerState::Alive {position, boost, armor: armor - 1}
+        match &mut self.state {
+            PlayerState::Alive { armor, .. } => {
+                if *armor > 0 {
+                    *armor -= 1;
                 } else {
-                    PlayerState::Dead
-                };
-                self.set_state(new_state);
-
+                    self.state = PlayerState::Dead;
+                }
                 Ok(())
             }
             PlayerState::Dead => { Err(()) }
@@ -115,7 +315,7 @@ impl Player {
 impl Display for Player {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self.state {
-            PlayerState::Alive { position, boost, armor } => {
+            PlayerState::Alive { position, boost, armor, .. } => {
                 write!(f, "Player({}: {}-{} @{})", self.id, boost, armor, position)
             }
             PlayerState::Dead => {
@@ -1,29 +1,361 @@
-use std::cmp::max;
-use std::collections::{BinaryHeap, HashMap, VecDeque};
-use std::fmt::{Display, Formatter, Pointer, write};
-use std::time::Instant;
-use crate::board::{Board, BoardCell, CellState};
-use crate::player::{Action, ACTIONS, PlayerState};
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use tracing::{debug, debug_span, trace, warn};
+use crate::board::{Board, CellState};
+use crate::clock::TimeControl;
+use crate::network::Network;
+use crate::observation::{encode, N_PLANES};
+use crate::player::{Action, Player, PlayerState};
 use crate::position::Position;
+use crate::search_info::SearchInfo;
+use crate::transposition::TranspositionTable;
+use crate::visibility::UnknownCells;
+use crate::worker::CancelToken;
 use crate::power_up::PowerUp;
 
-pub trait PlayerController {
+/// Decides moves for one player. Controllers are `Send` so they can run on a worker thread,
+/// see `ThreadedController`.
+pub trait PlayerController: Send {
     fn get_action(&mut self, board: &Board, player_id: usize) -> Action;
+
+    /// Called before the controller's turn when the game runs on a clock, with the time left
+    /// for it. Moves that take longer are penalised by `Game`.
+    fn set_clock(&mut self, _remaining: Duration, _time_control: TimeControl) {}
+
+    /// Called before `get_action` when the controller runs on a worker thread. Searches should
+    /// poll the token and return their best move so far once it is cancelled.
+    fn set_cancel_token(&mut self, _token: CancelToken) {}
+
+    /// Whether the controller thinks about its `predict`ions in the background while the
+    /// opponent moves, as a `ThreadedController` does. `Game` only asks those to predict.
+    fn can_ponder(&self) -> bool {
+        false
+    }
+
+    /// Predicts `opponent_id`'s next move on `board`, which is the position just after this
+    /// controller's own move, to think about while the opponent does. `None`, the default,
+    /// opts out of pondering.
+    fn predict(&mut self, _board: &Board, _player_id: usize, _opponent_id: usize) -> Option<Action> {
+        None
+    }
+
+    /// Thinks about the predicted position until `token` is cancelled. Only called on a worker
+    /// thread, see `ThreadedController`, right after a successful `predict`.
+    fn ponder(&mut self, _token: &CancelToken) {}
+
+    /// The opponent played the predicted move, so work done while pondering still applies.
+    fn ponder_hit(&mut self) {}
+
+    /// The opponent played something else; work done while pondering should be dropped.
+    fn ponder_miss(&mut self) {}
+
+    /// The score the last `get_action` search settled on, for controllers that search.
+    fn search_score(&self) -> Option<i32> {
+        None
+    }
+
+    /// Where searching controllers send their `SearchInfo` reports from now on.
+    fn set_info_sink(&mut self, _sink: Sender<SearchInfo>) {}
+
+    /// What `get_action` should be shown in cells the player has never seen, when the board's
+    /// `Visibility` hides part of it.
+    fn unknown_cells(&self) -> UnknownCells {
+        UnknownCells::Open
+    }
+}
+
+/// Placeholder for a seat whose moves are supplied through `Game::run_turn_with`.
+pub struct ExternalController {}
+
+impl PlayerController for ExternalController {
+    fn get_action(&mut self, _board: &Board, _player_id: usize) -> Action {
+        Action::Up
+    }
+}
+
+/// Builds a controller from a spec such as `clockwise`, `bfs`, `bfs:250` (turn time in
+/// milliseconds), `bfs:250:1,3,4,5` (with `ZoneWeights`), `net:<weights>`, `net:250:<weights>` or
+/// `bfs-net:250:<weights>`. Searching controllers take a thread count after `@`, as in
+/// `bfs@4:250`, and how to treat cells hidden by fog of war after `/`, as in `bfs/blocked:250`,
+/// see `UnknownCells`.
+#[allow(clippy::result_unit_err)]
+pub fn parse_controller(spec: &str) -> Result<Box<dyn PlayerController>, ()> {
+    let load = |path: &str| {
+        Network::load(Path::new(path)).ok()
+            .filter(|network| network.input_shape().0 == N_PLANES)
+            .ok_or(())
+    };
+    let (name, args) = match spec.split_once(':') {
+        Some((name, args)) => { (name, Some(args)) }
+        None => { (spec, None) }
+    };
+    let (name, unknown_cells) = match name.split_once('/') {
+        Some((name, unknown_cells)) => { (name, Some(unknown_cells.parse()?)) }
+        None => { (name, None) }
+    };
+    let (name, threads) = match name.split_once('@') {
+        Some((name, threads)) => { (name, threads.parse().map_err(|_| ())?) }
+        None => { (name, 1) }
+    };
+    let searching = |mut controller: BFSController| -> Result<Box<dyn PlayerController>, ()> {
+        controller.threads = threads;
+        controller.unknown_cells = unknown_cells.unwrap_or_default();
+        Ok(Box::new(controller))
+    };
+    match (name, args) {
+        ("clockwise", None) if threads == 1 && unknown_cells.is_none() => { Ok(Box::new(ClockwiseController {})) }
+        ("bfs", None) => { searching(BFSController::new(1000)) }
+        ("bfs", Some(args)) => {
+            let (turn_time, weights) = match args.split_once(':') {
+                Some((turn_time, weights)) => {
+                    let weights: Result<Vec<i32>, _> = weights.split(',').map(|weight| weight.parse()).collect();
+                    (turn_time, ZoneWeights::from_slice(&weights.map_err(|_| ())?)?)
+                }
+                None => { (args, ZoneWeights::default()) }
+            };
+            let turn_time = turn_time.parse().map_err(|_| ())?;
+            searching(BFSController::with_evaluator(turn_time, Box::new(VoronoiEvaluator { weights })))
+        }
+        ("net", Some(args)) if threads == 1 && unknown_cells.is_none() => {
+            let (turn_time, path) = match args.split_once(':').map(|(turn_time, path)| (turn_time.parse(), path)) {
+                Some((Ok(turn_time), path)) => { (turn_time, path) }
+                _ => { (1000, args) }
+            };
+            Ok(Box::new(NetworkController::new(load(path)?, turn_time)?))
+        }
+        ("bfs-net", Some(args)) => {
+            let (turn_time, path) = args.split_once(':').ok_or(())?;
+            let turn_time = turn_time.parse().map_err(|_| ())?;
+            let evaluator = NetworkEvaluator { network: load(path)? };
+            searching(BFSController::with_evaluator(turn_time, Box::new(evaluator)))
+        }
+        _ => { Err(()) }
+    }
+}
+
+fn is_safe(board: &Board, player_id: usize, action: Action) -> bool {
+    match board.apply_action(player_id, action) {
+        Ok(new_board) => {
+            matches!(new_board.players()[player_id].get_state(), PlayerState::Alive { .. })
+        }
+        Err(_) => { false }
+    }
+}
+
+/// A random legal move that keeps `player_id` alive, or any legal move if none does.
+pub fn random_safe_action<R: Rng>(board: &Board, player_id: usize, rng: &mut R) -> Action {
+    let mut safe = Vec::new();
+    let legal = board.legal_actions(player_id);
+    for &action in &legal {
+        if let Ok(new_board) = board.apply_action(player_id, action) {
+            if let PlayerState::Alive { .. } = new_board.players()[player_id].get_state() {
+                safe.push(action);
+            }
+        }
+    }
+    if safe.is_empty() {
+        legal.get(rng.gen_range(0..legal.len().max(1))).copied().unwrap_or(Action::Up)
+    } else {
+        safe[rng.gen_range(0..safe.len())]
+    }
+}
+
+/// Turns `NetworkController` plays without its network, once a forward pass is expected to
+/// overrun, before timing the network again.
+const NETWORK_RETRY_TURNS: usize = 8;
+
+/// Plays the safe action with the highest policy logit from a `Network`. Logits follow
+/// `Board::actions` order.
+pub struct NetworkController {
+    pub network: Network,
+    /// While a forward pass is expected to take longer than this, the network is skipped for
+    /// the first safe move.
+    pub turn_time_milliseconds: u128,
+    /// Set from the game clock, tightening `turn_time_milliseconds`.
+    pub time_budget: Option<Duration>,
+    /// How long a forward pass is expected to take: timed once up front, then on every pass.
+    inference: Duration,
+    /// Turns the network has been skipped for in a row.
+    skipped: usize,
+}
+
+#[allow(clippy::result_unit_err)]
+impl NetworkController {
+    /// Returns `Err` if `network` does not take the `N_PLANES` planes of an `Observation`.
+    /// A calibration pass is timed here, so even the first move keeps to the turn time.
+    pub fn new(network: Network, turn_time_milliseconds: u128) -> Result<Self, ()> {
+        let (planes, height, width) = network.input_shape();
+        if planes != N_PLANES {
+            return Err(())
+        }
+        let started = Instant::now();
+        network.forward(&vec![0.0; planes * height * width])?;
+        let inference = started.elapsed();
+        Ok(NetworkController { network, turn_time_milliseconds, time_budget: None, inference, skipped: 0 })
+    }
+}
+
+impl PlayerController for NetworkController {
+    fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
+        let turn_time = Duration::from_millis(self.turn_time_milliseconds.min(u64::MAX as u128) as u64);
+        let turn_time = self.time_budget.map_or(turn_time, |budget| budget.min(turn_time));
+        let logits = if self.inference > turn_time && self.skipped < NETWORK_RETRY_TURNS {
+            self.skipped += 1;
+            debug!(player_id, inference = ?self.inference, ?turn_time, "network too slow, skipped");
+            Vec::new()
+        } else {
+            self.skipped = 0;
+            let started = Instant::now();
+            match self.network.evaluate(&encode(board, player_id)) {
+                Ok(logits) => {
+                    self.inference = started.elapsed();
+                    logits
+                }
+                Err(_) => {
+                    warn!(player_id, shape = ?self.network.input_shape(), "network does not fit the board, skipped");
+                    Vec::new()
+                }
+            }
+        };
+        let mut best = None;
+        for (index, action) in board.actions().iter().enumerate() {
+            if !is_safe(board, player_id, *action) {
+                continue
+            }
+            let logit = logits.get(index + 1).copied().unwrap_or(0.0);
+            match best {
+                Some((_, best_logit)) if best_logit >= logit => {}
+                _ => { best = Some((*action, logit)) }
+            }
+        }
+        match best {
+            Some((action, _)) => { action }
+            None => { Action::Up }
+        }
+    }
+
+    fn set_clock(&mut self, remaining: Duration, time_control: TimeControl) {
+        self.time_budget = Some(time_control.move_budget(remaining));
+    }
+}
+
+/// Scores a position for `BFSController`; higher is better for `player_id`. Only called while
+/// every player is alive.
+pub trait Evaluator: Send {
+    fn evaluate(&mut self, board: &Board, player_id: usize) -> i32;
+
+    /// A copy for another search thread.
+    fn box_clone(&self) -> Box<dyn Evaluator>;
+}
+
+/// What each reachable cell is worth to whoever gets there first in `calc_zone_relative`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ZoneWeights {
+    pub empty: i32,
+    pub double_speed: i32,
+    pub armor: i32,
+    pub bomb: i32,
+    pub phase: i32,
+    pub eraser: i32,
+    pub freeze: i32,
+    pub teleport: i32,
+}
+
+impl Default for ZoneWeights {
+    fn default() -> Self {
+        ZoneWeights { empty: 1, double_speed: 3, armor: 4, bomb: 5, phase: 3, eraser: 2, freeze: 4, teleport: 2 }
+    }
+}
+
+#[allow(clippy::result_unit_err)]
+impl ZoneWeights {
+    /// The same weight for every cell.
+    pub fn uniform(weight: i32) -> Self {
+        ZoneWeights::from_slice(&[weight; 8]).unwrap()
+    }
+
+    pub fn to_vec(&self) -> Vec<i32> {
+        vec![self.empty, self.double_speed, self.armor, self.bomb, self.phase, self.eraser, self.freeze, self.teleport]
+    }
+
+    /// What a cell holding `power_up` is worth, and what holding it in an inventory is worth.
+    pub fn power_up(&self, power_up: &PowerUp) -> i32 {
+        match power_up {
+            PowerUp::DoubleSpeed { .. } => { self.double_speed }
+            PowerUp::Armor => { self.armor }
+            PowerUp::Bomb => { self.bomb }
+            PowerUp::Phase { .. } => { self.phase }
+            PowerUp::Eraser { .. } => { self.eraser }
+            PowerUp::Freeze => { self.freeze }
+            PowerUp::Teleport => { self.teleport }
+        }
+    }
+
+    /// Reads weights in `to_vec` order. Four values, from before the later power-ups, leave
+    /// those at their defaults.
+    pub fn from_slice(values: &[i32]) -> Result<Self, ()> {
+        match values {
+            [empty, double_speed, armor, bomb] => {
+                Ok(ZoneWeights { empty: *empty, double_speed: *double_speed, armor: *armor, bomb: *bomb, ..ZoneWeights::default() })
+            }
+            [empty, double_speed, armor, bomb, phase, eraser, freeze, teleport] => {
+                Ok(ZoneWeights { empty: *empty, double_speed: *double_speed, armor: *armor, bomb: *bomb,
+                                 phase: *phase, eraser: *eraser, freeze: *freeze, teleport: *teleport })
+            }
+            _ => { Err(()) }
+        }
+    }
+}
+
+/// Territory lead by Voronoi partition, see `calc_zone_relative`.
+#[derive(Default)]
+pub struct VoronoiEvaluator {
+    pub weights: ZoneWeights,
+}
+
+impl Evaluator for VoronoiEvaluator {
+    fn evaluate(&mut self, board: &Board, player_id: usize) -> i32 {
+        calc_zone_relative_weighted(board, player_id, &self.weights)
+    }
+
+    fn box_clone(&self) -> Box<dyn Evaluator> {
+        Box::new(VoronoiEvaluator { weights: self.weights })
+    }
+}
+
+/// The value output of a `Network`, scaled so a value of 1 scores 1000.
+pub struct NetworkEvaluator {
+    pub network: Network,
+}
+
+impl Evaluator for NetworkEvaluator {
+    fn evaluate(&mut self, board: &Board, player_id: usize) -> i32 {
+        match self.network.evaluate(&encode(board, player_id)) {
+            Ok(output) => { (output[0] * 1000.0).clamp(MIN_SCORE as f32 + 1.0, MAX_SCORE as f32 - 1.0) as i32 }
+            Err(_) => { 0 }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Evaluator> {
+        Box::new(NetworkEvaluator { network: self.network.clone() })
+    }
 }
 
 pub struct ClockwiseController {}
 
 impl PlayerController for ClockwiseController {
     fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
-        for action in ACTIONS {
-            match board.apply_action(player_id, action) {
-                Ok(new_board) => {
-                    match new_board.players()[player_id].get_state() {
-                        PlayerState::Alive { .. } => { return action }
-                        PlayerState::Dead => {}
-                    }
+        for &action in board.actions() {
+            if let Ok(new_board) = board.apply_action(player_id, action) {
+                match new_board.players()[player_id].get_state() {
+                    PlayerState::Alive { .. } => { return action }
+                    PlayerState::Dead => {}
                 }
-                Err(_) => {}
             }
         }
 
@@ -37,6 +369,8 @@ const MAX_SCORE: i32 = i32::MAX;
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 struct SearchNode {
     scores: Vec<i32>,
+    /// Breaks ties between equal scores; helper threads each order them their own way.
+    order: u64,
     actions: Vec<Action>,
     state: Board,
 }
@@ -48,107 +382,423 @@ impl Display for SearchNode {
 }
 
 pub struct BFSController {
-    pub(crate) turn_time_milliseconds: u128,
-    pub(crate) score_tracker: HashMap<String, i32>,
+    pub turn_time_milliseconds: u128,
+    pub score_tracker: TranspositionTable,
+    /// Search threads, each searching the whole tree, see `search`.
+    pub threads: usize,
+    /// Positions scored by the last search, over all threads.
+    pub last_nodes: u64,
+    /// Threads the last search ran on.
+    pub last_threads: usize,
+    pub last_score: Option<i32>,
+    pub evaluator: Box<dyn Evaluator>,
+    /// Set from the game clock; the search stops at whichever of this and
+    /// `turn_time_milliseconds` comes first.
+    pub time_budget: Option<Duration>,
+    pub cancel_token: Option<CancelToken>,
+    pub info_sink: Option<Sender<SearchInfo>>,
+    /// What the search takes cells hidden by fog of war to be.
+    pub unknown_cells: UnknownCells,
+    /// The position `predict` expects next and who moves in it.
+    predicted: Option<(Board, usize)>,
+    /// The position pondered and what the search on it found.
+    pondered: Option<(Board, SearchResult)>,
+    /// The opponent played the predicted move, so the next search starts from `pondered`.
+    ponder_hit: bool,
 }
 
-impl PlayerController for BFSController {
-    fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
-        println!("searching {}", player_id);
-        let mut queue = BinaryHeap::new();
+impl BFSController {
+    pub fn new(turn_time_milliseconds: u128) -> Self {
+        BFSController::with_evaluator(turn_time_milliseconds, Box::new(VoronoiEvaluator::default()))
+    }
 
-        let initial_node = SearchNode {
-            scores: Vec::from([score(board, player_id, &mut self.score_tracker)]),
-            actions: Vec::new(),
-            state: board.clone(),
+    pub fn with_evaluator(turn_time_milliseconds: u128, evaluator: Box<dyn Evaluator>) -> Self {
+        BFSController { turn_time_milliseconds, score_tracker: TranspositionTable::new(), threads: 1, last_nodes: 0,
+                        last_threads: 0, last_score: None, evaluator, time_budget: None, cancel_token: None, info_sink: None,
+                        unknown_cells: UnknownCells::Open, predicted: None, pondered: None, ponder_hit: false }
+    }
+}
+
+impl BFSController {
+    /// Best-first search from `board` until `turn_time` runs out, `cancel_token` is cancelled
+    /// or the tree is exhausted. Returns the best move found, with its worst-case score and
+    /// line and the search's totals, and reports progress to `info_sink` along the way. A
+    /// `seed` from an earlier search of the same position is carried on rather than redone.
+    ///
+    /// With several `threads` the search is Lazy SMP: every thread searches the whole tree, each
+    /// helper starting from a rotated root and breaking ties between equal scores in its own
+    /// order, so the threads spread out over the tree while reading and filling the shared
+    /// `score_tracker`. The best worst-case score over all threads wins.
+    fn search(&mut self, board: &Board, player_id: usize, turn_time: u128,
+              cancel_token: Option<CancelToken>, pondering: bool, seed: Option<&SearchResult>) -> SearchResult {
+        let start_time = Instant::now();
+        let span = &debug_span!("search", player_id, pondering);
+        let _entered = span.enter();
+        let cancelled = || cancel_token.as_ref().is_some_and(|token| token.is_cancelled());
+        let root = board.legal_actions(player_id);
+        let threads = self.threads.max(1);
+        let roots: Vec<Vec<Action>> = (0..threads)
+            .map(|thread| {
+                let mut rotated = root.clone();
+                rotated.rotate_left(thread % root.len().max(1));
+                rotated
+            })
+            .collect();
+        let table = &self.score_tracker;
+        let sink = &self.info_sink;
+        let info = |stats: &SearchStats, best_line: &[Action], score: i32, done: bool| {
+            let elapsed = start_time.elapsed();
+            SearchInfo {
+                player_id,
+                depth: stats.depth,
+                nodes: stats.nodes,
+                nodes_per_second: stats.nodes as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+                elapsed,
+                best_line: best_line.to_vec(),
+                score,
+                cache_hit_rate: stats.hits as f64 / stats.nodes.max(1) as f64,
+                pondering,
+                done,
+            }
+        };
+        // Only the first thread reports improvements; its figures stand in for the others'.
+        let mut report = |stats: &SearchStats, best_line: &[Action], score: i32| {
+            if let Some(sink) = sink {
+                let _ = sink.send(info(stats, best_line, score, false));
+            }
         };
-        queue.push(initial_node);
+        let results: Vec<SearchResult> = if threads == 1 {
+            vec![best_first(board, player_id, &root, 0, seed, turn_time, &cancelled, table, self.evaluator.as_mut(), &mut report)]
+        } else {
+            let helpers: Vec<Box<dyn Evaluator>> = (1..threads).map(|_| self.evaluator.box_clone()).collect();
+            let evaluator = self.evaluator.as_mut();
+            thread::scope(|scope| {
+                let handles: Vec<_> = helpers.into_iter().zip(&roots[1..]).enumerate()
+                    .map(|(index, (mut helper, root))| scope.spawn(move || span.in_scope(|| {
+                        best_first(board, player_id, root, index + 1, None, turn_time, &cancelled, table, helper.as_mut(), &mut |_, _, _| {})
+                    })))
+                    .collect();
+                let mut results = vec![best_first(board, player_id, &roots[0], 0, seed, turn_time, &cancelled, table, evaluator, &mut report)];
+                results.extend(handles.into_iter().map(|handle| handle.join().unwrap()));
+                results
+            })
+        };
+        let mut stats = SearchStats::default();
+        for result in &results {
+            stats.nodes += result.stats.nodes;
+            stats.hits += result.stats.hits;
+            stats.depth = stats.depth.max(result.stats.depth);
+        }
+        self.last_nodes = stats.nodes;
+        self.last_threads = threads;
+        let best = results.iter().skip(1).fold(&results[0], |best, result| {
+            if result.score > best.score { result } else { best }
+        });
+        debug!(nodes = stats.nodes, depth = stats.depth, score = best.score, action = ?best.action, "search done");
+        if let Some(sink) = sink {
+            let _ = sink.send(info(&stats, &best.best_line, best.score, true));
+        }
+        SearchResult { stats, ..best.clone() }
+    }
+}
 
-        let mut best_so_far = Action::Up;
-        let mut score_so_far = MIN_SCORE;
+/// Counters kept by one search thread.
+#[derive(Debug, Copy, Clone, Default)]
+struct SearchStats {
+    /// Positions scored.
+    nodes: u64,
+    /// Of those, how many were already in the transposition table.
+    hits: u64,
+    /// Our moves along the deepest line expanded.
+    depth: usize,
+}
 
-        let start_time = Instant::now();
-        'full: while queue.len() > 0 && start_time.elapsed().as_millis() < self.turn_time_milliseconds {
-            let SearchNode { scores: _scores, actions: _actions, state } = queue.pop().unwrap();
-            for us_action in ACTIONS {
-                let mut actions = _actions.clone();
-                actions.push(us_action);
-
-                let them_state = match state.apply_action(player_id, us_action) {
-                    Ok(board) => {
-                        match board.players()[player_id].get_state() {
-                            PlayerState::Alive { .. } => { board }
-                            PlayerState::Dead => { continue }
-                        }
-                    }
-                    Err(_) => {
-                        //println!("invalid us action: {:?}\n{}", us_action, state);
-                        continue;
-                    }
-                };
-                let mut worst_node = SearchNode { scores: Vec::new(), actions: Vec::new(), state: them_state.clone() };
-                let mut worst_score = MAX_SCORE;
-                let them_id = 1-player_id;
-                for them_action in ACTIONS {
-                    let us_state = match them_state.apply_action(them_id, them_action) {
-                        Ok(board) => {
-                            board
-                        }
-                        Err(_) => {
-                            them_state.clone()
-                        }
-                    };
-                    let new_score = score(&us_state, player_id, &mut self.score_tracker);
-                    let mut scores = _scores.clone();
-                    scores.push(new_score);
-                    let new_node = SearchNode { scores, actions: actions.clone(), state: us_state };
-
-                    if new_score < worst_score {
-                        worst_score = new_score;
-                        worst_node = new_node;
-                    }
-                }
-                if worst_score > score_so_far {
-                    println!("{}", worst_node);
-                    best_so_far = actions[0];
-                    score_so_far = worst_score;
+#[derive(Debug, Clone)]
+struct SearchResult {
+    action: Action,
+    score: i32,
+    best_line: Vec<Action>,
+    stats: SearchStats,
+}
 
-                    if worst_score == MAX_SCORE {
-                        break 'full;
-                    }
-                }
+/// One thread's best-first search, trying only `root_actions` as the first move, in that
+/// order. Returns the best first move and the line and worst-case score behind it, calling
+/// `report` each time the best score improves. The next opponent in turn order stands in for
+/// every other player. Thread 0 expands equal scores in move order, helpers in an order of
+/// their own. A `seed` line is expanded first and its move taken as the best so far.
+#[allow(clippy::too_many_arguments)]
+fn best_first(board: &Board, player_id: usize, root_actions: &[Action], thread: usize,
+              seed: Option<&SearchResult>, turn_time: u128,
+              cancelled: &(dyn Fn() -> bool + Sync), table: &TranspositionTable,
+              evaluator: &mut dyn Evaluator,
+              report: &mut dyn FnMut(&SearchStats, &[Action], i32)) -> SearchResult {
+    let mut queue = BinaryHeap::new();
+    let mut stats = SearchStats::default();
+
+    let initial_node = SearchNode {
+        scores: Vec::from([score(board, player_id, table, evaluator, &mut stats)]),
+        order: 0,
+        actions: Vec::new(),
+        state: board.clone(),
+    };
+
+    let mut best_so_far = root_actions.first().copied().unwrap_or(Action::Up);
+    let mut score_so_far = MIN_SCORE;
+    let mut line_so_far = Vec::new();
+    let them_id = match next_opponent(board, player_id) {
+        Some(them_id) => { them_id }
+        None => { return SearchResult { action: best_so_far, score: score_so_far, best_line: line_so_far, stats } }
+    };
+    let mut expander = Expander { player_id, them_id, thread, table, evaluator, stats };
 
-                queue.push(worst_node);
+    // Walk the seed's line, queueing the positions along it so the search carries on from
+    // its far end, and start from the seed's move.
+    let mut node = initial_node.clone();
+    for us_action in seed.map(|seed| seed.best_line.as_slice()).unwrap_or_default() {
+        if node.actions.is_empty() && !root_actions.contains(us_action) {
+            break
+        }
+        match expander.expand(&node, *us_action) {
+            Some((next, _)) => {
+                queue.push(next.clone());
+                node = next;
             }
+            None => { break }
         }
+    }
+    if let Some(seed) = seed.filter(|seed| root_actions.contains(&seed.action)) {
+        best_so_far = seed.action;
+        score_so_far = seed.score;
+        line_so_far = seed.best_line.clone();
+        expander.stats.depth = expander.stats.depth.max(seed.stats.depth);
+    }
+    queue.push(initial_node);
+
+    let start_time = Instant::now();
+    'full: while !queue.is_empty() && start_time.elapsed().as_millis() < turn_time && !cancelled() {
+        let node = queue.pop().unwrap();
+        trace!(depth = node.actions.len(), queued = queue.len(), nodes = expander.stats.nodes, "expand");
+        let us_actions = if node.actions.is_empty() { root_actions.to_vec() } else { node.state.legal_actions(player_id) };
+        for us_action in us_actions {
+            let (worst_node, worst_score) = match expander.expand(&node, us_action) {
+                Some(expanded) => { expanded }
+                None => { continue }
+            };
+            if worst_score > score_so_far {
+                best_so_far = worst_node.actions[0];
+                score_so_far = worst_score;
+                line_so_far = worst_node.actions.clone();
+                debug!(score = score_so_far, line = ?line_so_far, nodes = expander.stats.nodes, "best line improved");
+                report(&expander.stats, &line_so_far, score_so_far);
+
+                if worst_score == MAX_SCORE {
+                    break 'full;
+                }
+            }
 
-        println!("best score: {}", score_so_far);
-        best_so_far
+            queue.push(worst_node);
+        }
     }
+
+    SearchResult { action: best_so_far, score: score_so_far, best_line: line_so_far, stats: expander.stats }
 }
 
-fn score(board: &Board, player_id: usize, tracker: &mut HashMap<String, i32>) -> i32 {
-    let key = &format!("{}{}", player_id, board);
-    if !tracker.contains_key(key) {
-        let _score = match board.players()[player_id].get_state() {
-            PlayerState::Alive { .. } => {
-                match board.players()[1-player_id].get_state() {
-                    PlayerState::Alive { .. } => {
-                        calc_zone_relative(board, player_id)
-                    }
-                    PlayerState::Dead => { MAX_SCORE }
+/// What one search thread needs to expand nodes.
+struct Expander<'a> {
+    player_id: usize,
+    them_id: usize,
+    thread: usize,
+    table: &'a TranspositionTable,
+    evaluator: &'a mut dyn Evaluator,
+    stats: SearchStats,
+}
+
+impl Expander<'_> {
+    /// Plays `us_action` from `node`, answered by the opponent's reply that scores worst for
+    /// us. Returns that position and its score, or `None` if the move is illegal or fatal.
+    fn expand(&mut self, node: &SearchNode, us_action: Action) -> Option<(SearchNode, i32)> {
+        let mut actions = node.actions.clone();
+        actions.push(us_action);
+        self.stats.depth = self.stats.depth.max(actions.len());
+
+        let them_state = match node.state.apply_action(self.player_id, us_action) {
+            Ok(board) => {
+                match board.players()[self.player_id].get_state() {
+                    PlayerState::Alive { .. } => { board }
+                    PlayerState::Dead => { return None }
                 }
             }
-            PlayerState::Dead => { MIN_SCORE }
+            Err(_) => {
+                trace!(action = ?us_action, "invalid action");
+                return None
+            }
+        };
+        let order = if self.thread == 0 { 0 } else { TranspositionTable::key(&(self.thread, &actions)) };
+        let mut worst_node = SearchNode { scores: Vec::new(), order, actions: actions.clone(), state: them_state.clone() };
+        let mut worst_score = MAX_SCORE;
+        for them_action in them_state.legal_actions(self.them_id) {
+            let us_state = match them_state.apply_action(self.them_id, them_action) {
+                Ok(board) => {
+                    board
+                }
+                Err(_) => {
+                    them_state.clone()
+                }
+            };
+            let new_score = score(&us_state, self.player_id, self.table, self.evaluator, &mut self.stats);
+            let mut scores = node.scores.clone();
+            scores.push(new_score);
+            let new_node = SearchNode { scores, order, actions: actions.clone(), state: us_state };
+
+            if new_score < worst_score {
+                worst_score = new_score;
+                worst_node = new_node;
+            }
+        }
+        Some((worst_node, worst_score))
+    }
+}
+
+impl PlayerController for BFSController {
+    fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
+        let turn_time = match self.time_budget {
+            Some(budget) => { budget.as_millis().min(self.turn_time_milliseconds) }
+            None => { self.turn_time_milliseconds }
+        };
+        // After a ponder hit the search picks up where pondering left off, as long as the
+        // position is the one pondered, which fog of war may have changed.
+        let seed = match (self.pondered.take(), std::mem::take(&mut self.ponder_hit)) {
+            (Some((pondered_board, result)), true) if pondered_board == *board => { Some(result) }
+            _ => { None }
         };
-        tracker.insert(key.clone(), _score);
+        let result = self.search(board, player_id, turn_time, self.cancel_token.clone(), false, seed.as_ref());
+        self.last_score = Some(result.score);
+        result.action
+    }
+
+    /// Expects the opponent reply that scores worst for us, looking one move ahead.
+    fn predict(&mut self, board: &Board, player_id: usize, opponent_id: usize) -> Option<Action> {
+        let mut prediction = None;
+        let mut worst_score = MAX_SCORE;
+        for action in board.legal_actions(opponent_id) {
+            let next = match board.apply_action(opponent_id, action) {
+                Ok(next) => { next }
+                Err(_) => { continue }
+            };
+            let next_score = score(&next, player_id, &self.score_tracker, self.evaluator.as_mut(), &mut SearchStats::default());
+            if prediction.is_none() || next_score < worst_score {
+                worst_score = next_score;
+                prediction = Some((action, next));
+            }
+        }
+        let (action, next) = prediction?;
+        self.predicted = Some((next, player_id));
+        self.pondered = None;
+        self.ponder_hit = false;
+        Some(action)
+    }
+
+    /// Searches the predicted position, filling `score_tracker` and keeping the best line for
+    /// the real search.
+    fn ponder(&mut self, token: &CancelToken) {
+        if let Some((board, player_id)) = self.predicted.take() {
+            let result = self.search(&board, player_id, u128::MAX, Some(token.clone()), true, None);
+            self.pondered = Some((board, result));
+        }
+    }
+
+    fn ponder_hit(&mut self) {
+        self.ponder_hit = self.pondered.is_some();
+    }
+
+    fn ponder_miss(&mut self) {
+        self.predicted = None;
+        self.pondered = None;
+        self.ponder_hit = false;
+    }
+
+    fn set_clock(&mut self, remaining: Duration, time_control: TimeControl) {
+        self.time_budget = Some(time_control.move_budget(remaining));
+    }
+
+    fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel_token = Some(token);
+    }
+
+    fn set_info_sink(&mut self, sink: Sender<SearchInfo>) {
+        self.info_sink = Some(sink);
+    }
+
+    fn search_score(&self) -> Option<i32> {
+        self.last_score
     }
-    let _score = *tracker.get(key).unwrap();
-    //println!("{}\n{} score: {}", board, player_id, _score);
+
+    fn unknown_cells(&self) -> UnknownCells {
+        self.unknown_cells
+    }
+}
+
+fn score(board: &Board, player_id: usize, table: &TranspositionTable, evaluator: &mut dyn Evaluator,
+         stats: &mut SearchStats) -> i32 {
+    stats.nodes += 1;
+    // The whole board, so held power-ups, armor, boosts, headings and fading trails all count.
+    let key = TranspositionTable::key(&(player_id, board));
+    if let Some(score) = table.get(key) {
+        stats.hits += 1;
+        return score
+    }
+    let _score = match board.players()[player_id].get_state() {
+        PlayerState::Alive { .. } => {
+            match next_opponent(board, player_id) {
+                Some(_) => { evaluator.evaluate(board, player_id) }
+                None => { MAX_SCORE }
+            }
+        }
+        PlayerState::Dead => { MIN_SCORE }
+    };
+    table.insert(key, _score);
+    trace!(player_id, score = _score, "scored");
     _score
 }
 
-fn calc_zone_relative(board: &Board, player_id: usize) -> i32 {
+/// The first living player after `player_id` in turn order who is not on their team.
+pub(crate) fn next_opponent(board: &Board, player_id: usize) -> Option<usize> {
+    let n_players = board.players().len();
+    (1..n_players)
+        .map(|offset| (player_id + offset) % n_players)
+        .find(|id| !board.allies(player_id, *id) && board.players()[*id].get_state() != PlayerState::Dead)
+}
+
+pub(crate) fn calc_zone_relative(board: &Board, player_id: usize) -> i32 {
+    calc_zone_relative_weighted(board, player_id, &ZoneWeights::default())
+}
+
+pub(crate) fn calc_zone_relative_weighted(board: &Board, player_id: usize, weights: &ZoneWeights) -> i32 {
+    let mut scores = zone_scores(board, weights);
+    let alive = |player: &Player| matches!(player.get_state(), PlayerState::Alive { .. });
+    for (score, player) in scores.iter_mut().zip(board.players()) {
+        if alive(player) {
+            *score += player.inventory().items().map(|power_up| weights.power_up(&power_up)).sum::<i32>();
+        }
+    }
+    // Allies share their territory, scored against the strongest other team still playing.
+    let mut team_scores: Vec<Option<i32>> = vec![None; scores.len()];
+    for (score, player) in scores.into_iter().zip(board.players()) {
+        if alive(player) {
+            *team_scores[player.team()].get_or_insert(0) += score;
+        }
+    }
+    let player_score = team_scores[board.team(player_id)].take().unwrap_or(0);
+    let max_score = team_scores.into_iter().flatten().max().unwrap_or(0);
+    trace!(player_id, player_score, max_score, "zone scores");
+    player_score - max_score
+}
+
+/// Each player's weighted Voronoi territory: the cells they reach strictly first. Fading trails
+/// count as open once they will have faded by the time the player gets there.
+pub(crate) fn zone_scores(board: &Board, weights: &ZoneWeights) -> Vec<i32> {
+    // Every player moves once in this many turns.
+    let round = board.players().len();
     let mut seen: Vec<Vec<(Position, u16)>> = Vec::new();
     let mut frontiers: Vec<VecDeque<(Position, u16)>> = Vec::new();
     let mut scores = Vec::new();
@@ -166,27 +816,26 @@ fn calc_zone_relative(board: &Board, player_id: usize) -> i32 {
     }
     while !frontiers_empty(&frontiers) {
         for id in 0..frontiers.len() {
-            let mut frontier = &mut frontiers[id];
+            let frontier = &mut frontiers[id];
             for _ in 0..frontier.len() {
                 let (position, n_steps) = match frontier.pop_front() {
                     None => { continue }
                     Some(pos) => { pos }
                 };
-                //println!("position: {}", position);
 
                 let score = match board.get_cell(position) {
                     Ok(cell) => {
                         match cell.get_state() {
-                            CellState::Empty => { 1 }
-                            CellState::PowerUp { power_up } => {
-                                match power_up {
-                                    PowerUp::DoubleSpeed { duration } => { 3 }
-                                    PowerUp::Armor => { 4 }
-                                    PowerUp::Bomb => { 5 }
+                            CellState::Empty => { weights.empty }
+                            CellState::PowerUp { power_up } => { weights.power_up(&power_up) }
+                            CellState::Wall => { continue }
+                            CellState::Owned { .. } => {
+                                let arrival = board.turn() + n_steps as usize * round;
+                                match cell.expires() {
+                                    Some(turn) if turn <= arrival => { weights.empty }
+                                    _ => { continue }
                                 }
                             }
-                            CellState::Wall => { continue }
-                            CellState::Owned { .. } => { continue }
                             CellState::Occupied { .. } => { 0 }
                         }
                     }
@@ -208,21 +857,12 @@ fn calc_zone_relative(board: &Board, player_id: usize) -> i32 {
 
                 scores[id] += score;
 
-
-                'outer: for (row_off, col_off) in [(-1, 0), (0, 1), (1, 0), (0, -1)] {
-                    let new_position = match position.offset(row_off, col_off) {
-                        Ok(pos) => {
-                            pos
-                        }
-                        Err(_) => { continue }
-                    };
-                    //println!("{}", new_position);
+                'outer: for new_position in board.neighbors(position) {
 
                     match is_seen(new_position, &seen) {
                         Some(_) => { continue }
                         None => {
                             for (pos, _) in frontier.iter() {
-                                //println!("{} == {} : {}", *pos, new_position, *pos == new_position);
                                 if *pos == new_position {
                                     continue 'outer;
                                 }
@@ -230,20 +870,12 @@ fn calc_zone_relative(board: &Board, player_id: usize) -> i32 {
                         }
                     }
 
-                    //println!("{}:  {}, {}", id, new_position, n_steps + 1);
                     frontier.push_back((new_position, n_steps + 1));
                 }
             }
         }
     }
-    let player_score = scores[player_id];
-    scores[player_id] = 0;
-    let mut max_score = i32::MIN;
-    for score in scores {
-        max_score = max_score.max(score);
-    }
-    //println!("{}: player / max scores: {} / {}", player_id, player_score, max_score);
-    player_score - max_score
+    scores
 }
 
 /*
@@ -260,7 +892,7 @@ score_relative(board, player_id)
 */
 
 
-fn is_seen(position: Position, seen: &Vec<Vec<(Position, u16)>>) -> Option<(usize, u16)> {
+fn is_seen(position: Position, seen: &[Vec<(Position, u16)>]) -> Option<(usize, u16)> {
     for (id, seen_vec) in seen.iter().enumerate() {
         for (_position, n_steps) in seen_vec {
             if position == *_position {
@@ -271,9 +903,9 @@ fn is_seen(position: Position, seen: &Vec<Vec<(Position, u16)>>) -> Option<(usiz
     None
 }
 
-fn frontiers_empty(frontiers: &Vec<VecDeque<(Position, u16)>>) -> bool {
+fn frontiers_empty(frontiers: &[VecDeque<(Position, u16)>]) -> bool {
     for frontier in frontiers {
-        if frontier.len() > 0 {
+        if !frontier.is_empty() {
             return false;
         }
     }
@@ -320,4 +952,89 @@ while time() < end_time:
 [ *much stuff* ] []
 
 
- */
\ No newline at end of file
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A network of one dense layer taking `N_PLANES` planes of `height` x `width`, every
+    /// weight zero.
+    fn zero_network(height: usize, width: usize) -> Network {
+        let inputs = N_PLANES * height * width;
+        let outputs = 5;
+        let zeros = |count: usize| vec!["0"; count].join(" ");
+        let text = format!("tron-net 1\ninput {} {} {}\ndense {} {}\n{}\n{}\n",
+                           N_PLANES, height, width, inputs, outputs, zeros(inputs * outputs), zeros(outputs));
+        Network::parse(&text).unwrap()
+    }
+
+    #[test]
+    fn zones_are_scored_against_teams_still_playing() {
+        let mut board = Board::with_spawns(1, 7, &[Position::new(0, 0), Position::new(0, 3), Position::new(0, 6)]).unwrap();
+        board.set_teams(&[0, 1, 1]).unwrap();
+        // With empty cells worth less than nothing, every team's territory scores below zero.
+        let weights = ZoneWeights { empty: -1, ..ZoneWeights::default() };
+        let scores = zone_scores(&board, &weights);
+        assert!(scores[1] + scores[2] < 0);
+        assert_eq!(calc_zone_relative_weighted(&board, 0, &weights), scores[0] - scores[1] - scores[2]);
+
+        let mut player = board.players()[2];
+        let mut inventory = player.inventory();
+        inventory.add(PowerUp::Bomb, 1).unwrap();
+        player.set_inventory(inventory);
+        board.set_player(player).unwrap();
+        let board = board.eliminate_player(2).unwrap();
+        let scores = zone_scores(&board, &weights);
+        assert_eq!(calc_zone_relative_weighted(&board, 0, &weights), scores[0] - scores[1]);
+    }
+
+    #[test]
+    fn network_controllers_need_observation_planes() {
+        let tiny = Network::parse(include_str!("../tests/fixtures/tiny.net")).unwrap();
+        assert!(NetworkController::new(tiny, 100).is_err());
+        assert!(NetworkController::new(zero_network(4, 4), 100).is_ok());
+    }
+
+    #[test]
+    fn slow_networks_are_timed_again_after_a_few_turns() {
+        let board = Board::new_default(4, 4).unwrap();
+        let mut controller = NetworkController::new(zero_network(4, 4), 0).unwrap();
+        controller.inference = Duration::from_secs(1);
+        for turn in 0..NETWORK_RETRY_TURNS {
+            controller.get_action(&board, 0);
+            assert_eq!(controller.skipped, turn + 1);
+        }
+        controller.get_action(&board, 0);
+        assert_eq!(controller.skipped, 0);
+        assert!(controller.inference < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn ponder_hits_carry_the_pondered_search_over() {
+        let board = Board::new_default(6, 6).unwrap().apply_action(0, Action::Right).unwrap();
+        let mut controller = BFSController::new(0);
+        let predicted = controller.predict(&board, 0, 1).unwrap();
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        controller.ponder(&token);
+        let (_, pondered) = controller.pondered.clone().unwrap();
+        assert!(pondered.score > MIN_SCORE);
+        assert!(!pondered.best_line.is_empty());
+
+        // With no time of its own the search can only answer with what pondering found.
+        let next = board.apply_action(1, predicted).unwrap();
+        controller.ponder_hit();
+        assert_eq!(controller.get_action(&next, 0), pondered.action);
+        assert_eq!(controller.last_score, Some(pondered.score));
+        assert!(controller.pondered.is_none());
+
+        controller.predict(&board, 0, 1).unwrap();
+        controller.ponder_miss();
+        controller.get_action(&next, 0);
+        assert_eq!(controller.last_score, Some(MIN_SCORE));
+    }
+}
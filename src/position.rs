@@ -1,11 +1,12 @@
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Position {
     pub row: usize,
     pub column: usize
 }
 
+#[allow(clippy::result_unit_err)]
 impl Position {
     pub fn new(row: usize, column: usize) -> Self {
         Self { row, column }
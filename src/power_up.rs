@@ -1,8 +1,106 @@
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+use rand::Rng;
+
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum PowerUp {
     DoubleSpeed {
         duration: usize
     },
     Armor,
-    Bomb
-}
\ No newline at end of file
+    Bomb,
+    /// Passes through trails unharmed for `duration` of the player's own turns.
+    Phase {
+        duration: usize
+    },
+    /// Wipes the `length` oldest cells of the player's own trail.
+    Eraser {
+        length: usize
+    },
+    /// Every opponent skips their next turn.
+    Freeze,
+    /// Jumps to an open cell elsewhere on the board, leaving the old head as trail.
+    Teleport,
+}
+
+/// How many kinds of power-up there are, see `PowerUp::kind`.
+pub const N_POWER_UPS: usize = 7;
+
+impl PowerUp {
+    /// An index per kind of power-up, ignoring parameters, below `N_POWER_UPS`.
+    pub fn kind(&self) -> usize {
+        match self {
+            PowerUp::DoubleSpeed { .. } => { 0 }
+            PowerUp::Armor => { 1 }
+            PowerUp::Bomb => { 2 }
+            PowerUp::Phase { .. } => { 3 }
+            PowerUp::Eraser { .. } => { 4 }
+            PowerUp::Freeze => { 5 }
+            PowerUp::Teleport => { 6 }
+        }
+    }
+
+    /// The character standing for this power-up on a printed board.
+    pub fn symbol(&self) -> char {
+        match self {
+            PowerUp::DoubleSpeed { .. } => { 'S' }
+            PowerUp::Armor => { 'A' }
+            PowerUp::Bomb => { 'B' }
+            PowerUp::Phase { .. } => { 'P' }
+            PowerUp::Eraser { .. } => { 'E' }
+            PowerUp::Freeze => { 'F' }
+            PowerUp::Teleport => { 'T' }
+        }
+    }
+}
+
+/// A power-up of a uniformly random kind, with the standard parameters.
+pub fn random_power_up<R: Rng>(rng: &mut R) -> PowerUp {
+    match rng.gen_range(0..N_POWER_UPS) {
+        0 => { PowerUp::DoubleSpeed { duration: 3 } }
+        1 => { PowerUp::Armor }
+        2 => { PowerUp::Bomb }
+        3 => { PowerUp::Phase { duration: 3 } }
+        4 => { PowerUp::Eraser { length: 5 } }
+        5 => { PowerUp::Freeze }
+        _ => { PowerUp::Teleport }
+    }
+}
+/// The most power-ups a player can hold, see `Board::set_inventory_slots`.
+pub const MAX_INVENTORY_SLOTS: usize = 4;
+
+/// Power-ups a player has collected but not yet used, oldest first.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Inventory {
+    slots: [Option<PowerUp>; MAX_INVENTORY_SLOTS],
+}
+
+#[allow(clippy::result_unit_err)]
+impl Inventory {
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots[0].is_none()
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = PowerUp> + '_ {
+        self.slots.iter().map_while(|slot| *slot)
+    }
+
+    /// Stores `power_up` if fewer than `capacity` slots are taken.
+    pub fn add(&mut self, power_up: PowerUp, capacity: usize) -> Result<(), ()> {
+        let len = self.len();
+        if len >= capacity.min(MAX_INVENTORY_SLOTS) {
+            return Err(())
+        }
+        self.slots[len] = Some(power_up);
+        Ok(())
+    }
+
+    /// Removes and returns the power-up held longest.
+    pub fn take_oldest(&mut self) -> Option<PowerUp> {
+        let oldest = self.slots[0].take()?;
+        self.slots.rotate_left(1);
+        Some(oldest)
+    }
+}
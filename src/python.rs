@@ -0,0 +1,843 @@
+// The pyo3 0.22 `#[pymethods]` expansion converts every `PyResult` error into itself.
+#![allow(clippy::useless_conversion)]
+
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::blast::Blast;
+use crate::board::{Board, CellState};
+use crate::clock::TimeControl;
+use crate::environment::{default_board, Environment, RewardConfig, StepResult, VecEnvironment};
+use crate::event::{DeathCause, GameEvent, GameObserver};
+use crate::game::{EndReason, Game, GameState};
+use crate::logging;
+use crate::map_generator::{generate, MapConfig};
+use crate::observation::{encode, Observation};
+use crate::player::{Action, PlayerState, RelativeAction};
+use crate::player_controller::{BFSController, ClockwiseController, PlayerController};
+use crate::position::Position;
+use crate::rules::Rules;
+use crate::search_info::SearchInfo;
+use crate::sudden_death::SuddenDeath;
+use crate::turn_limit::TurnLimit;
+use crate::visibility::PlayerView;
+
+const ODD_HEX_TORUS: &str = "hex grids that wrap rows need an even height";
+
+fn invalid(message: &str) -> PyErr {
+    PyValueError::new_err(message.to_string())
+}
+
+/// A `Rules` preset by name, or else rules written as TOML.
+fn parse_rules(rules: &str) -> PyResult<Rules> {
+    match Rules::preset(rules) {
+        Some(rules) => { Ok(rules) }
+        None => { Rules::parse(rules).map_err(|err| invalid(&err.to_string())) }
+    }
+}
+
+#[pyclass(name = "Board", module = "rust_tron")]
+#[derive(Clone)]
+pub struct PyBoard {
+    board: Board,
+}
+
+#[pymethods]
+impl PyBoard {
+    #[staticmethod]
+    fn new_default(height: usize, width: usize) -> PyResult<Self> {
+        match Board::new_default(height, width) {
+            Ok(board) => { Ok(PyBoard { board }) }
+            Err(_) => { Err(invalid("board dimensions must be non-zero")) }
+        }
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        self.board.height()
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.board.width()
+    }
+
+    /// The rules the board is played by, as TOML. Set to a preset name, `classic`,
+    /// `powerups` or `arcade`, or to TOML with any of the keys read back here, optionally
+    /// starting from `preset = "<name>"`.
+    #[getter]
+    fn rules(&self) -> String {
+        self.board.rules().to_string()
+    }
+
+    #[setter]
+    fn set_rules(&mut self, rules: &str) -> PyResult<()> {
+        self.board.set_rules(parse_rules(rules)?).map_err(|_| invalid(ODD_HEX_TORUS))
+    }
+
+    /// Each player's team, by player id. Set to a list with one team per player, numbered
+    /// from 0.
+    #[getter]
+    fn teams(&self) -> Vec<usize> {
+        self.board.teams()
+    }
+
+    #[setter]
+    fn set_teams(&mut self, teams: Vec<usize>) -> PyResult<()> {
+        self.board.set_teams(&teams).map_err(|_| invalid("teams must list one team per player, numbered from 0"))
+    }
+
+    /// One of `bounded`, `torus` or `cylinder`.
+    #[getter]
+    fn topology(&self) -> String {
+        self.board.topology().to_string()
+    }
+
+    #[setter]
+    fn set_topology(&mut self, topology: &str) -> PyResult<()> {
+        match topology.parse() {
+            Ok(topology) => { self.board.set_topology(topology).map_err(|_| invalid(ODD_HEX_TORUS)) }
+            Err(_) => { Err(invalid("topology must be bounded, torus or cylinder")) }
+        }
+    }
+
+    /// `square` or `hex`.
+    #[getter]
+    fn grid(&self) -> String {
+        self.board.grid().to_string()
+    }
+
+    #[setter]
+    fn set_grid(&mut self, grid: &str) -> PyResult<()> {
+        match grid.parse() {
+            Ok(grid) => { self.board.set_grid(grid).map_err(|_| invalid(ODD_HEX_TORUS)) }
+            Err(_) => { Err(invalid("grid must be square or hex")) }
+        }
+    }
+
+    /// The moves players can make on this board.
+    fn actions(&self) -> Vec<Action> {
+        self.board.actions().to_vec()
+    }
+
+    /// `absolute` or `relative`.
+    #[getter]
+    fn steering(&self) -> String {
+        self.board.steering().to_string()
+    }
+
+    #[setter]
+    fn set_steering(&mut self, steering: &str) -> PyResult<()> {
+        match steering.parse() {
+            Ok(steering) => {
+                self.board.set_steering(steering);
+                Ok(())
+            }
+            Err(_) => { Err(invalid("steering must be absolute or relative")) }
+        }
+    }
+
+    /// `(start_turn, interval)` of the shrinking arena, or `None` when it never shrinks.
+    #[getter]
+    fn sudden_death(&self) -> Option<(usize, usize)> {
+        self.board.sudden_death().map(|rule| (rule.start_turn, rule.interval))
+    }
+
+    #[setter]
+    fn set_sudden_death(&mut self, sudden_death: Option<(usize, usize)>) -> PyResult<()> {
+        match sudden_death {
+            Some((_, 0)) => { Err(invalid("sudden death interval must be positive")) }
+            Some((start_turn, interval)) => {
+                self.board.set_sudden_death(Some(SuddenDeath { start_turn, interval }));
+                Ok(())
+            }
+            None => {
+                self.board.set_sudden_death(None);
+                Ok(())
+            }
+        }
+    }
+
+    /// `(turns, tiebreak)` ending long games, tiebreak one of `draw`, `cells`, `territory` or
+    /// `armor`; `None` for no limit.
+    #[getter]
+    fn turn_limit(&self) -> Option<(usize, String)> {
+        self.board.turn_limit().map(|limit| (limit.turns, limit.tiebreak.to_string()))
+    }
+
+    #[setter]
+    fn set_turn_limit(&mut self, turn_limit: Option<(usize, String)>) -> PyResult<()> {
+        match turn_limit {
+            Some((turns, tiebreak)) => {
+                let tiebreak = tiebreak.parse()
+                    .map_err(|_| invalid("tiebreak must be draw, cells, territory or armor"))?;
+                self.board.set_turn_limit(Some(TurnLimit { turns, tiebreak }));
+            }
+            None => { self.board.set_turn_limit(None) }
+        }
+        Ok(())
+    }
+
+    /// Power-ups each player can hold and use with `Action.Use`, which always fires the oldest
+    /// one; 0 makes them take effect on pickup.
+    #[getter]
+    fn inventory_slots(&self) -> usize {
+        self.board.inventory_slots()
+    }
+
+    #[setter]
+    fn set_inventory_slots(&mut self, slots: usize) {
+        self.board.set_inventory_slots(slots);
+    }
+
+    /// `(shape, radius, damage, chain)` of bomb blasts: shape one of `square`, `plus` or
+    /// `diamond`, whether caught players take damage, and whether bombs in range go off too.
+    #[getter]
+    fn blast(&self) -> (String, usize, bool, bool) {
+        let blast = self.board.blast();
+        (blast.shape.to_string(), blast.radius, blast.damage, blast.chain)
+    }
+
+    #[setter]
+    fn set_blast(&mut self, blast: (String, usize, bool, bool)) -> PyResult<()> {
+        let (shape, radius, damage, chain) = blast;
+        let shape = shape.parse().map_err(|_| invalid("blast shape must be square, plus or diamond"))?;
+        self.board.set_blast(Blast { shape, radius, damage, chain });
+        Ok(())
+    }
+
+    #[getter]
+    fn turn(&self) -> usize {
+        self.board.turn()
+    }
+
+    /// The turn the cell at (row, column) turns into a wall, or `None` if it never does.
+    fn closing_turn(&self, row: usize, column: usize) -> Option<usize> {
+        self.board.closing_turn(Position::new(row, column))
+    }
+
+    /// The turn the fading trail at (row, column) turns empty again, or `None` if the cell
+    /// isn't a fading trail.
+    fn trail_expires(&self, row: usize, column: usize) -> PyResult<Option<usize>> {
+        match self.board.get_cell(Position::new(row, column)) {
+            Ok(cell) => { Ok(cell.expires()) }
+            Err(_) => { Err(invalid("position is off the board")) }
+        }
+    }
+
+    fn legal_actions(&self, player_id: usize) -> Vec<Action> {
+        self.board.legal_actions(player_id)
+    }
+
+    fn relative_action(&self, player_id: usize, relative: RelativeAction) -> PyResult<Action> {
+        match self.board.relative_action(player_id, relative) {
+            Ok(action) => { Ok(action) }
+            Err(_) => { Err(invalid("player has no heading yet")) }
+        }
+    }
+
+    /// The cell at (row, column) as `(kind, detail)`, e.g. `("owned", 1)` or `("power_up", "Bomb")`.
+    fn cell(&self, row: usize, column: usize) -> PyResult<(String, Option<String>)> {
+        let cell = match self.board.get_cell(Position::new(row, column)) {
+            Ok(cell) => { cell }
+            Err(_) => { return Err(invalid("position is off the board")) }
+        };
+        Ok(match cell.get_state() {
+            CellState::Empty => { ("empty".to_string(), None) }
+            CellState::PowerUp { power_up } => { ("power_up".to_string(), Some(format!("{:?}", power_up))) }
+            CellState::Wall => { ("wall".to_string(), None) }
+            CellState::Owned { player_id } => { ("owned".to_string(), Some(player_id.to_string())) }
+            CellState::Occupied { player_id } => { ("occupied".to_string(), Some(player_id.to_string())) }
+        })
+    }
+
+    /// One dict per player with `alive` and `team`, and `row`, `column`, `boost`, `armor`, `phase`,
+    /// `frozen`, `heading` and `inventory`, the held power-ups oldest first, while alive.
+    fn players<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let mut players = Vec::new();
+        for player in self.board.players() {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("team", player.team())?;
+            match player.get_state() {
+                PlayerState::Alive { position, boost, armor, heading, phase, frozen } => {
+                    dict.set_item("alive", true)?;
+                    dict.set_item("row", position.row)?;
+                    dict.set_item("column", position.column)?;
+                    dict.set_item("boost", boost)?;
+                    dict.set_item("armor", armor)?;
+                    dict.set_item("phase", phase)?;
+                    dict.set_item("frozen", frozen)?;
+                    dict.set_item("heading", heading.map(|heading| heading.into_py(py)))?;
+                    let inventory: Vec<String> = player.inventory().items().map(|power_up| format!("{:?}", power_up)).collect();
+                    dict.set_item("inventory", inventory)?;
+                }
+                PlayerState::Dead => {
+                    dict.set_item("alive", false)?;
+                }
+            }
+            players.push(dict);
+        }
+        Ok(players)
+    }
+
+    fn apply_action(&self, player_id: usize, action: Action) -> PyResult<Self> {
+        if player_id >= self.board.players().len() {
+            return Err(invalid("no such player"))
+        }
+        match self.board.apply_action(player_id, action) {
+            Ok(board) => { Ok(PyBoard { board }) }
+            Err(_) => { Err(invalid("action is not legal for this player")) }
+        }
+    }
+
+    fn encode(&self, player_id: usize) -> PyResult<PyObservation> {
+        if player_id >= self.board.players().len() {
+            return Err(invalid("no such player"))
+        }
+        Ok(PyObservation { observation: encode(&self.board, player_id) })
+    }
+
+    fn __str__(&self) -> String {
+        self.board.to_string()
+    }
+}
+
+#[pyclass(name = "Observation", module = "rust_tron")]
+pub struct PyObservation {
+    observation: Observation,
+}
+
+#[pymethods]
+impl PyObservation {
+    /// `(planes, height, width)`, for reshaping `data`.
+    #[getter]
+    fn shape(&self) -> (usize, usize, usize) {
+        (self.observation.planes, self.observation.height, self.observation.width)
+    }
+
+    #[getter]
+    fn data(&self) -> Vec<f32> {
+        self.observation.data.clone()
+    }
+}
+
+fn py_observations(observations: Vec<Observation>) -> Vec<PyObservation> {
+    observations.into_iter().map(|observation| PyObservation { observation }).collect()
+}
+
+#[pyclass(name = "BFSController", module = "rust_tron")]
+#[derive(Clone)]
+pub struct PyBFSController {
+    #[pyo3(get, set)]
+    turn_time_milliseconds: u128,
+    #[pyo3(get, set)]
+    threads: usize,
+}
+
+#[pymethods]
+impl PyBFSController {
+    #[new]
+    #[pyo3(signature = (turn_time_milliseconds=1000, threads=1))]
+    fn new(turn_time_milliseconds: u128, threads: usize) -> Self {
+        PyBFSController { turn_time_milliseconds, threads }
+    }
+}
+
+#[pyclass(name = "ClockwiseController", module = "rust_tron")]
+#[derive(Clone)]
+pub struct PyClockwiseController {}
+
+#[pymethods]
+impl PyClockwiseController {
+    #[new]
+    fn new() -> Self {
+        PyClockwiseController {}
+    }
+}
+
+/// Calls `get_action(board, player_id)` on a Python object. Errors raised by the callback are
+/// printed and the player moves `Up`.
+struct PythonController {
+    callback: Py<PyAny>,
+}
+
+impl PlayerController for PythonController {
+    fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
+        Python::with_gil(|py| {
+            let result = self.callback
+                .call_method1(py, "get_action", (PyBoard { board: board.clone() }, player_id))
+                .and_then(|action| action.extract::<Action>(py));
+            match result {
+                Ok(action) => { action }
+                Err(err) => {
+                    err.print(py);
+                    Action::Up
+                }
+            }
+        })
+    }
+
+    /// Forwards the remaining time in seconds to the object's `set_clock`, if it has one.
+    fn set_clock(&mut self, remaining: Duration, _time_control: TimeControl) {
+        Python::with_gil(|py| {
+            if self.callback.bind(py).hasattr("set_clock").unwrap_or(false) {
+                if let Err(err) = self.callback.call_method1(py, "set_clock", (remaining.as_secs_f64(),)) {
+                    err.print(py);
+                }
+            }
+        })
+    }
+}
+
+/// Builds a Rust controller from a Python value: the Rust controller classes map to their
+/// engine counterparts, anything else is driven through its `get_action` method.
+fn controller_from(controller: &Bound<'_, PyAny>) -> Box<dyn PlayerController> {
+    if let Ok(bfs) = controller.extract::<PyBFSController>() {
+        let mut controller = BFSController::new(bfs.turn_time_milliseconds);
+        controller.threads = bfs.threads;
+        Box::new(controller)
+    } else if controller.extract::<PyClockwiseController>().is_ok() {
+        Box::new(ClockwiseController {})
+    } else {
+        Box::new(PythonController { callback: controller.clone().unbind() })
+    }
+}
+
+/// Whether `controller_from` drives `controller` through Python calls.
+fn is_python_callback(controller: &Bound<'_, PyAny>) -> bool {
+    controller.extract::<PyBFSController>().is_err() && controller.extract::<PyClockwiseController>().is_err()
+}
+
+fn state_dict<'py>(py: Python<'py>, state: &GameState) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    match state {
+        GameState::Active { turn, alive_ids } => {
+            dict.set_item("active", true)?;
+            dict.set_item("turn", *turn)?;
+            dict.set_item("alive_ids", alive_ids.clone())?;
+        }
+        GameState::Over { winner_id, winning_team, reason } => {
+            dict.set_item("active", false)?;
+            dict.set_item("winner_id", *winner_id)?;
+            dict.set_item("winning_team", *winning_team)?;
+            dict.set_item("reason", reason_name(reason))?;
+        }
+    }
+    Ok(dict)
+}
+
+fn reason_name(reason: &EndReason) -> String {
+    match reason {
+        EndReason::LastSurvivor => { "last_survivor".to_string() }
+        EndReason::MutualElimination => { "mutual_elimination".to_string() }
+        EndReason::TurnLimit { tiebreak } => { format!("turn_limit:{}", tiebreak) }
+    }
+}
+
+/// A `GameEvent` as a dict with its `type`, e.g. `"moved"` or `"player_died"`, and fields.
+/// Positions are `(row, column)` tuples.
+fn event_dict<'py>(py: Python<'py>, event: &GameEvent) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    let cell = |position: &Position| (position.row, position.column);
+    match event {
+        GameEvent::Moved { player_id, action, from, to } => {
+            dict.set_item("type", "moved")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("action", action.into_py(py))?;
+            dict.set_item("from", cell(from))?;
+            dict.set_item("to", cell(to))?;
+        }
+        GameEvent::PowerUpCollected { player_id, power_up, position } => {
+            dict.set_item("type", "power_up_collected")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("power_up", format!("{:?}", power_up))?;
+            dict.set_item("position", cell(position))?;
+        }
+        GameEvent::PowerUpActivated { player_id, power_up, position } => {
+            dict.set_item("type", "power_up_activated")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("power_up", format!("{:?}", power_up))?;
+            dict.set_item("position", cell(position))?;
+        }
+        GameEvent::ArmorAbsorbedHit { player_id, position, armor_left } => {
+            dict.set_item("type", "armor_absorbed_hit")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("position", cell(position))?;
+            dict.set_item("armor_left", *armor_left)?;
+        }
+        GameEvent::BombExploded { player_id, position, cleared_cells } => {
+            dict.set_item("type", "bomb_exploded")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("position", cell(position))?;
+            dict.set_item("cleared_cells", cleared_cells.iter().map(cell).collect::<Vec<_>>())?;
+        }
+        GameEvent::TrailErased { player_id, cleared_cells } => {
+            dict.set_item("type", "trail_erased")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("cleared_cells", cleared_cells.iter().map(cell).collect::<Vec<_>>())?;
+        }
+        GameEvent::TrailFaded { cleared_cells } => {
+            dict.set_item("type", "trail_faded")?;
+            dict.set_item("cleared_cells", cleared_cells.iter().map(cell).collect::<Vec<_>>())?;
+        }
+        GameEvent::Teleported { player_id, from, to } => {
+            dict.set_item("type", "teleported")?;
+            dict.set_item("player_id", *player_id)?;
+            dict.set_item("from", cell(from))?;
+            dict.set_item("to", cell(to))?;
+        }
+        GameEvent::TurnSkipped { player_id } => {
+            dict.set_item("type", "turn_skipped")?;
+            dict.set_item("player_id", *player_id)?;
+        }
+        GameEvent::PlayerDied { player_id, cause } => {
+            dict.set_item("type", "player_died")?;
+            dict.set_item("player_id", *player_id)?;
+            let cause = match cause {
+                DeathCause::Wall => { "wall".to_string() }
+                DeathCause::Trail { owner_id } => { format!("trail:{}", owner_id) }
+                DeathCause::Collision { other_id } => { format!("collision:{}", other_id) }
+                DeathCause::Blast { owner_id } => { format!("blast:{}", owner_id) }
+                DeathCause::SuddenDeath => { "sudden_death".to_string() }
+                DeathCause::Forfeit => { "forfeit".to_string() }
+            };
+            dict.set_item("cause", cause)?;
+        }
+        GameEvent::GameEnded { winner_id, winning_team, reason } => {
+            dict.set_item("type", "game_ended")?;
+            dict.set_item("winner_id", *winner_id)?;
+            dict.set_item("winning_team", *winning_team)?;
+            dict.set_item("reason", reason_name(reason))?;
+        }
+    }
+    Ok(dict)
+}
+
+/// Calls a Python callable with each event as a dict, see `event_dict`.
+struct PythonObserver {
+    callback: Py<PyAny>,
+}
+
+impl GameObserver for PythonObserver {
+    fn on_event(&mut self, event: &GameEvent) {
+        Python::with_gil(|py| {
+            if let Err(err) = event_dict(py, event).and_then(|dict| self.callback.call1(py, (dict,))) {
+                err.print(py);
+            }
+        })
+    }
+}
+
+#[pyclass(name = "Game", module = "rust_tron", unsendable)]
+pub struct PyGame {
+    game: Game,
+    search_info: Option<Receiver<SearchInfo>>,
+}
+
+#[pymethods]
+impl PyGame {
+    #[new]
+    fn new(board: PyBoard, controllers: Vec<Bound<'_, PyAny>>) -> PyResult<Self> {
+        let controllers = controllers.iter().map(controller_from).collect();
+        match Game::new(board.board, controllers) {
+            Ok(game) => { Ok(PyGame { game, search_info: None }) }
+            Err(_) => { Err(invalid("need exactly one controller per player")) }
+        }
+    }
+
+    #[getter]
+    fn board(&self) -> PyBoard {
+        PyBoard { board: self.game.board().clone() }
+    }
+
+    /// The board as `player_id` knows it under fog of war, taking cells they have never seen
+    /// to be `open` or `blocked`. The true board when the rules' visibility is `full`.
+    #[pyo3(signature = (player_id, unknown="open"))]
+    fn player_board(&self, player_id: usize, unknown: &str) -> PyResult<PyBoard> {
+        let unknown = unknown.parse().map_err(|_| invalid("unknown must be open or blocked"))?;
+        if player_id >= self.game.board().players().len() {
+            return Err(invalid("no such player"))
+        }
+        Ok(PyBoard { board: self.game.player_board(player_id, unknown).into_owned() })
+    }
+
+    /// Rows of flags for the cells `player_id` sees now, or `None` when the rules' visibility
+    /// is `full`.
+    fn visible(&self, player_id: usize) -> Option<Vec<Vec<bool>>> {
+        self.view_mask(player_id, PlayerView::is_visible)
+    }
+
+    /// Rows of flags for the cells `player_id` has ever seen, or `None` when the rules'
+    /// visibility is `full`.
+    fn known(&self, player_id: usize) -> Option<Vec<Vec<bool>>> {
+        self.view_mask(player_id, PlayerView::is_known)
+    }
+
+    fn state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        state_dict(py, self.game.state())
+    }
+
+    fn active_id(&self) -> Option<usize> {
+        self.game.active_id()
+    }
+
+    /// Runs every player on a clock: `"<base>+<increment>"` or `"<limit>/move"` in
+    /// milliseconds, or `None` for no clock. `overrun` is `forfeit`, `random` or `repeat`.
+    #[pyo3(signature = (time_control, overrun="forfeit"))]
+    fn set_time_control(&mut self, time_control: Option<&str>, overrun: &str) -> PyResult<()> {
+        let time_control = match time_control {
+            Some(time_control) => {
+                Some(time_control.parse().map_err(|_| invalid("time control must look like 60000+500 or 1000/move"))?)
+            }
+            None => { None }
+        };
+        let penalty = overrun.parse().map_err(|_| invalid("overrun must be forfeit, random or repeat"))?;
+        self.game.set_time_control(time_control, penalty);
+        Ok(())
+    }
+
+    /// Seconds left on `player_id`'s clock, or `None` without a clock.
+    fn remaining_time(&self, player_id: usize) -> Option<f64> {
+        self.game.remaining_time(player_id).map(|remaining| remaining.as_secs_f64())
+    }
+
+    /// Runs every controller on its own thread, playing a random safe move for any that takes
+    /// longer than `deadline_milliseconds`.
+    fn run_controllers_on_threads(&mut self, deadline_milliseconds: u64) {
+        self.game.run_controllers_on_threads(Duration::from_millis(deadline_milliseconds));
+    }
+
+    /// Calls `observer(event)` for every event from now on, with `event` a dict holding its
+    /// `type` (`moved`, `power_up_collected`, `power_up_activated`, `armor_absorbed_hit`,
+    /// `bomb_exploded`, `trail_erased`, `trail_faded`, `teleported`, `turn_skipped`,
+    /// `player_died` or `game_ended`) and fields.
+    fn add_observer(&mut self, observer: Py<PyAny>) {
+        self.game.add_observer(Box::new(PythonObserver { callback: observer }));
+    }
+
+    /// Starts collecting the controllers' search reports for `search_info`.
+    fn subscribe_search_info(&mut self) {
+        self.search_info = Some(self.game.subscribe_search_info());
+    }
+
+    /// The search reports sent since the last call, oldest first, as dicts with `player_id`,
+    /// `depth`, `nodes`, `nodes_per_second`, `elapsed` in seconds, `best_line`, `score`,
+    /// `cache_hit_rate`, `pondering` and `done`. Empty until `subscribe_search_info`.
+    fn search_info<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let mut reports = Vec::new();
+        for info in self.search_info.iter().flat_map(|receiver| receiver.try_iter()) {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("player_id", info.player_id)?;
+            dict.set_item("depth", info.depth)?;
+            dict.set_item("nodes", info.nodes)?;
+            dict.set_item("nodes_per_second", info.nodes_per_second)?;
+            dict.set_item("elapsed", info.elapsed.as_secs_f64())?;
+            dict.set_item("best_line", info.best_line.into_py(py))?;
+            dict.set_item("score", info.score)?;
+            dict.set_item("cache_hit_rate", info.cache_hit_rate)?;
+            dict.set_item("pondering", info.pondering)?;
+            dict.set_item("done", info.done)?;
+            reports.push(dict);
+        }
+        Ok(reports)
+    }
+
+    fn run_turn(&mut self, py: Python<'_>) -> PyResult<(usize, Vec<Action>)> {
+        // Python controllers on worker threads need the GIL while the game waits for them.
+        let game = &mut self.game;
+        match py.allow_threads(|| game.run_turn()) {
+            Ok(turn) => { Ok(turn) }
+            Err(_) => { Err(invalid("the turn could not be played")) }
+        }
+    }
+}
+
+impl PyGame {
+    fn view_mask(&self, player_id: usize, seen: fn(&PlayerView, Position) -> bool) -> Option<Vec<Vec<bool>>> {
+        let view = self.game.view(player_id)?;
+        let board = self.game.board();
+        Some((0..board.height())
+            .map(|row| (0..board.width()).map(|column| seen(view, Position::new(row, column))).collect())
+            .collect())
+    }
+}
+
+fn reward_config(win: f32, loss: f32, draw: f32, survival: f32, territory: f32) -> RewardConfig {
+    RewardConfig { win, loss, draw, survival, territory }
+}
+
+/// Controllers for `Environment`: `None` marks an agent seat.
+fn environment_controllers(controllers: Vec<Option<Bound<'_, PyAny>>>) -> Vec<Option<Box<dyn PlayerController>>> {
+    controllers.iter().map(|controller| controller.as_ref().map(controller_from)).collect()
+}
+
+type PyStepResult<'py> = (Vec<PyObservation>, Vec<f32>, bool, Bound<'py, PyDict>);
+
+fn step_tuple<'py>(py: Python<'py>, result: StepResult) -> PyResult<PyStepResult<'py>> {
+    let (observations, rewards, done, info) = result;
+    let dict = PyDict::new_bound(py);
+    dict.set_item("turn", info.turn)?;
+    dict.set_item("winner_id", info.winner_id)?;
+    dict.set_item("winning_team", info.winning_team)?;
+    let actions: Vec<(usize, Vec<PyObject>)> = info.actions.into_iter()
+        .map(|(player_id, actions)| (player_id, actions.into_iter().map(|action| action.into_py(py)).collect()))
+        .collect();
+    dict.set_item("actions", actions)?;
+    if let Some(terminal) = info.terminal_observations {
+        dict.set_item("terminal_observations", py_observations(terminal).into_py(py))?;
+    }
+    Ok((py_observations(observations), rewards, done, dict))
+}
+
+#[pyclass(name = "Environment", module = "rust_tron", unsendable)]
+pub struct PyEnvironment {
+    environment: Environment,
+}
+
+#[pymethods]
+impl PyEnvironment {
+    #[new]
+    #[pyo3(signature = (controllers, win=1.0, loss=-1.0, draw=0.0, survival=0.0, territory=0.0))]
+    fn new(controllers: Vec<Option<Bound<'_, PyAny>>>, win: f32, loss: f32, draw: f32, survival: f32, territory: f32) -> PyResult<Self> {
+        let reward = reward_config(win, loss, draw, survival, territory);
+        match Environment::new(environment_controllers(controllers), default_board, reward) {
+            Ok(environment) => { Ok(PyEnvironment { environment }) }
+            Err(_) => { Err(invalid("need exactly one controller per player")) }
+        }
+    }
+
+    #[getter]
+    fn agent_ids(&self) -> Vec<usize> {
+        self.environment.agent_ids().clone()
+    }
+
+    fn reset(&mut self, seed: u64) -> Vec<PyObservation> {
+        py_observations(self.environment.reset(seed))
+    }
+
+    fn step<'py>(&mut self, py: Python<'py>, actions: Vec<Action>) -> PyResult<PyStepResult<'py>> {
+        match self.environment.step(&actions) {
+            Ok(result) => { step_tuple(py, result) }
+            Err(_) => { Err(invalid("need exactly one action per agent")) }
+        }
+    }
+}
+
+#[pyclass(name = "VecEnvironment", module = "rust_tron", unsendable)]
+pub struct PyVecEnvironment {
+    environments: VecEnvironment,
+}
+
+#[pymethods]
+impl PyVecEnvironment {
+    /// `make_controllers()` is called once per environment and returns that environment's
+    /// controller list, as for `Environment`.
+    #[new]
+    #[pyo3(signature = (n_environments, make_controllers, win=1.0, loss=-1.0, draw=0.0, survival=0.0, territory=0.0))]
+    fn new(n_environments: usize, make_controllers: Bound<'_, PyAny>,
+           win: f32, loss: f32, draw: f32, survival: f32, territory: f32) -> PyResult<Self> {
+        let reward = reward_config(win, loss, draw, survival, territory);
+        let mut environments = Vec::new();
+        let mut calls_python = false;
+        for _ in 0..n_environments {
+            let controllers: Vec<Option<Bound<'_, PyAny>>> = make_controllers.call0()?.extract()?;
+            calls_python |= controllers.iter().flatten().any(is_python_callback);
+            match Environment::new(environment_controllers(controllers), default_board, reward) {
+                Ok(environment) => { environments.push(environment) }
+                Err(_) => { return Err(invalid("need exactly one controller per player")) }
+            }
+        }
+        let mut environments = VecEnvironment::new(environments);
+        // Python callbacks need the interpreter this thread holds, so they play in order.
+        if calls_python {
+            environments.set_threads(1);
+        }
+        Ok(PyVecEnvironment { environments })
+    }
+
+    fn __len__(&self) -> usize {
+        self.environments.len()
+    }
+
+    fn reset(&mut self, seed: u64) -> Vec<Vec<PyObservation>> {
+        self.environments.reset(seed).into_iter().map(py_observations).collect()
+    }
+
+    fn step<'py>(&mut self, py: Python<'py>, actions: Vec<Vec<Action>>) -> PyResult<Vec<PyStepResult<'py>>> {
+        match self.environments.step(&actions) {
+            Ok(results) => { results.into_iter().map(|result| step_tuple(py, result)).collect() }
+            Err(_) => { Err(invalid("need one action list per environment")) }
+        }
+    }
+}
+
+/// Generates an arena for 2 to 4 `players`, see `map_generator::generate`. `rules` is a preset name or
+/// TOML, see `Board.rules`; `topology` overrides the topology it sets.
+#[pyfunction]
+#[pyo3(signature = (seed, height=10, width=10, obstacle_density=0.1, symmetry="point", power_up_clusters=2, cluster_size=2, topology=None, grid="square", rules="powerups", players=2))]
+#[allow(clippy::too_many_arguments)]
+fn generate_map(seed: u64, height: usize, width: usize, obstacle_density: f64, symmetry: &str,
+                power_up_clusters: usize, cluster_size: usize, topology: Option<&str>, grid: &str,
+                rules: &str, players: usize) -> PyResult<PyBoard> {
+    let mut rules = parse_rules(rules)?;
+    if let Some(topology) = topology {
+        rules.topology = topology.parse().map_err(|_| invalid("topology must be bounded, torus or cylinder"))?;
+    }
+    let config = MapConfig {
+        height,
+        width,
+        grid: grid.parse().map_err(|_| invalid("grid must be square or hex"))?,
+        obstacle_density,
+        symmetry: symmetry.parse().map_err(|_| invalid("symmetry must be none, point, mirror or rotational"))?,
+        power_up_clusters,
+        cluster_size,
+        rules,
+        players,
+    };
+    if !config.grid.fits(config.rules.topology, config.height) {
+        return Err(invalid(ODD_HEX_TORUS))
+    }
+    if !config.symmetry.exact_on(config.grid, config.height, config.width) {
+        return Err(invalid("symmetry does not hold on this board: hex grids take none, or point with an even height, and rotational needs a square board"))
+    }
+    match generate(&config, &mut StdRng::seed_from_u64(seed)) {
+        Ok(board) => { Ok(PyBoard { board }) }
+        Err(_) => { Err(invalid("no arena fits these settings")) }
+    }
+}
+
+#[pyfunction(name = "encode")]
+fn py_encode(board: &PyBoard, player_id: usize) -> PyResult<PyObservation> {
+    board.encode(player_id)
+}
+
+/// Sends the engine's `tracing` events to stderr, filtered by `RUST_LOG`-style directives
+/// such as `"debug"` or `"warn,rust_tron::game=debug"`. `format` is `text` or `json`. Can
+/// only be called once per process.
+#[pyfunction]
+#[pyo3(signature = (filter="info", format="text"))]
+fn init_logging(filter: &str, format: &str) -> PyResult<()> {
+    let format = format.parse().map_err(|_| invalid("format must be text or json"))?;
+    logging::init(filter, format).map_err(|_| invalid("invalid filter, or logging is already set up"))
+}
+
+#[pymodule]
+fn rust_tron(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Action>()?;
+    m.add_class::<RelativeAction>()?;
+    m.add_class::<PyBoard>()?;
+    m.add_class::<PyObservation>()?;
+    m.add_class::<PyGame>()?;
+    m.add_class::<PyBFSController>()?;
+    m.add_class::<PyClockwiseController>()?;
+    m.add_class::<PyEnvironment>()?;
+    m.add_class::<PyVecEnvironment>()?;
+    m.add_function(wrap_pyfunction!(py_encode, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_map, m)?)?;
+    m.add_function(wrap_pyfunction!(init_logging, m)?)?;
+    Ok(())
+}
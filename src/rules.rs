@@ -0,0 +1,341 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use toml::{Table, Value};
+use crate::blast::Blast;
+use crate::player::Steering;
+use crate::sudden_death::SuddenDeath;
+use crate::topology::Topology;
+use crate::turn_limit::TurnLimit;
+use crate::visibility::Visibility;
+
+/// What running into a wall or a trail does.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Collision {
+    /// Costs a point of armor, or the player's life without armor.
+    #[default]
+    Damage,
+    /// Eliminates the player whatever their armor.
+    Kill,
+}
+
+impl FromStr for Collision {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "damage" => { Ok(Collision::Damage) }
+            "kill" => { Ok(Collision::Kill) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Collision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Collision::Damage => { write!(f, "damage") }
+            Collision::Kill => { write!(f, "kill") }
+        }
+    }
+}
+
+/// Who is eliminated when a player runs into another player's head.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum HeadOn {
+    /// Only the player who moved.
+    #[default]
+    Mover,
+    /// Both players.
+    Both,
+}
+
+impl FromStr for HeadOn {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mover" => { Ok(HeadOn::Mover) }
+            "both" => { Ok(HeadOn::Both) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for HeadOn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadOn::Mover => { write!(f, "mover") }
+            HeadOn::Both => { write!(f, "both") }
+        }
+    }
+}
+
+/// What becomes of the cells players leave behind.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum TrailPersistence {
+    /// Left cells stay trail for the rest of the game.
+    #[default]
+    Permanent,
+    /// Players leave no trail; left cells are empty again.
+    Off,
+    /// Left cells stay trail for `turns` turns, then are empty again, as in Snake.
+    Fading {
+        turns: usize
+    },
+}
+
+impl FromStr for TrailPersistence {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "permanent" => { Ok(TrailPersistence::Permanent) }
+            "off" => { Ok(TrailPersistence::Off) }
+            _ => {
+                let turns = s.strip_prefix("fading:").ok_or(())?.parse().map_err(|_| ())?;
+                if turns == 0 {
+                    return Err(())
+                }
+                Ok(TrailPersistence::Fading { turns })
+            }
+        }
+    }
+}
+
+impl Display for TrailPersistence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrailPersistence::Permanent => { write!(f, "permanent") }
+            TrailPersistence::Off => { write!(f, "off") }
+            TrailPersistence::Fading { turns } => { write!(f, "fading:{}", turns) }
+        }
+    }
+}
+
+/// The rules a `Board` is played by. The default is the `powerups` preset.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Rules {
+    pub topology: Topology,
+    pub steering: Steering,
+    pub wall: Collision,
+    pub trail: Collision,
+    pub head_on: HeadOn,
+    pub trails: TrailPersistence,
+    /// Teammates' trails and bombs hurt like anyone else's. Without it, players pass through
+    /// their teammates' trails and are spared by their blasts.
+    pub friendly_fire: bool,
+    /// Moves per turn while a speed boost lasts.
+    pub boost_moves: usize,
+    /// The most armor a player can stack, or `None` for no limit.
+    pub max_armor: Option<usize>,
+    pub blast: Blast,
+    /// Power-ups each player can hold for `Action::Use`, oldest used first; 0 fires them on pickup.
+    pub inventory_slots: usize,
+    /// Shrinks the arena late in the game, see `SuddenDeath`.
+    pub sudden_death: Option<SuddenDeath>,
+    /// Ends long games, deciding them by its tiebreak.
+    pub turn_limit: Option<TurnLimit>,
+    /// How much of the board controllers are shown, see `PlayerView`.
+    pub visibility: Visibility,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            topology: Topology::Bounded,
+            steering: Steering::Absolute,
+            wall: Collision::Damage,
+            trail: Collision::Damage,
+            head_on: HeadOn::Mover,
+            trails: TrailPersistence::Permanent,
+            friendly_fire: true,
+            boost_moves: 2,
+            max_armor: None,
+            blast: Blast::default(),
+            inventory_slots: 0,
+            sudden_death: None,
+            turn_limit: None,
+            visibility: Visibility::Full,
+        }
+    }
+}
+
+/// Names accepted by `Rules::preset`.
+pub const PRESETS: [&str; 3] = ["classic", "powerups", "arcade"];
+
+impl Rules {
+    /// A named rule set:
+    /// - `classic`: light cycles with relative steering, where any crash is fatal and
+    ///   head-on crashes take out both players.
+    /// - `powerups`: the default, where armor absorbs crashes into walls and trails.
+    /// - `arcade`: a wrapping arena with triple speed boosts, capped armor, an inventory and
+    ///   chaining bombs that hurt, decided on territory after 300 turns.
+    pub fn preset(name: &str) -> Option<Rules> {
+        match name {
+            "classic" => {
+                Some(Rules {
+                    steering: Steering::Relative,
+                    wall: Collision::Kill,
+                    trail: Collision::Kill,
+                    head_on: HeadOn::Both,
+                    max_armor: Some(0),
+                    ..Rules::default()
+                })
+            }
+            "powerups" => { Some(Rules::default()) }
+            "arcade" => {
+                Some(Rules {
+                    topology: Topology::Torus,
+                    boost_moves: 3,
+                    max_armor: Some(3),
+                    blast: "diamond:2:damage:chain".parse().ok()?,
+                    inventory_slots: 2,
+                    turn_limit: "300:territory".parse().ok(),
+                    ..Rules::default()
+                })
+            }
+            _ => { None }
+        }
+    }
+
+    /// A preset by name, or else the rules in the TOML file at `spec`.
+    pub fn resolve(spec: &str) -> io::Result<Rules> {
+        match Rules::preset(spec) {
+            Some(rules) => { Ok(rules) }
+            None => { Rules::load(Path::new(spec)) }
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Rules> {
+        Rules::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads rules from TOML with one key per field, e.g. `wall = "kill"` or `boost_moves = 3`.
+    /// Keys left out keep their value from `preset`, itself `powerups` if left out. Blasts,
+    /// sudden death and turn limits take the same specs as on the command line, and optional
+    /// rules can be turned off with `"none"`.
+    pub fn parse(text: &str) -> io::Result<Rules> {
+        let table: Table = text.parse().map_err(|err: toml::de::Error| invalid(err.message().to_string()))?;
+        let mut rules = match table.get("preset") {
+            Some(preset) => {
+                preset.as_str().and_then(Rules::preset)
+                    .ok_or_else(|| invalid(format!("preset must be one of {}", PRESETS.join(", "))))?
+            }
+            None => { Rules::default() }
+        };
+        for (key, value) in &table {
+            let parsed = match key.as_str() {
+                "preset" => { Ok(()) }
+                "topology" => { parse(value).map(|topology| rules.topology = topology) }
+                "steering" => { parse(value).map(|steering| rules.steering = steering) }
+                "wall" => { parse(value).map(|wall| rules.wall = wall) }
+                "trail" => { parse(value).map(|trail| rules.trail = trail) }
+                "head_on" => { parse(value).map(|head_on| rules.head_on = head_on) }
+                "trails" => { parse(value).map(|trails| rules.trails = trails) }
+                "friendly_fire" => { value.as_bool().ok_or(()).map(|friendly_fire| rules.friendly_fire = friendly_fire) }
+                "boost_moves" => { count(value).map(|moves| rules.boost_moves = moves) }
+                "max_armor" => {
+                    match value.as_str() {
+                        Some("none") => {
+                            rules.max_armor = None;
+                            Ok(())
+                        }
+                        _ => { count(value).map(|armor| rules.max_armor = Some(armor)) }
+                    }
+                }
+                "blast" => { parse(value).map(|blast| rules.blast = blast) }
+                "inventory_slots" => { count(value).map(|slots| rules.inventory_slots = slots) }
+                "sudden_death" => { optional(value).map(|sudden_death| rules.sudden_death = sudden_death) }
+                "turn_limit" => { optional(value).map(|turn_limit| rules.turn_limit = turn_limit) }
+                "visibility" => { parse(value).map(|visibility| rules.visibility = visibility) }
+                _ => { return Err(invalid(format!("unknown rule {}", key))) }
+            };
+            parsed.map_err(|_| invalid(format!("invalid value for {}: {}", key, value)))?;
+        }
+        Ok(rules)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse<T: FromStr>(value: &Value) -> Result<T, ()> {
+    value.as_str().ok_or(())?.parse().map_err(|_| ())
+}
+
+fn count(value: &Value) -> Result<usize, ()> {
+    usize::try_from(value.as_integer().ok_or(())?).map_err(|_| ())
+}
+
+fn optional<T: FromStr>(value: &Value) -> Result<Option<T>, ()> {
+    match value.as_str() {
+        Some("none") => { Ok(None) }
+        _ => { parse(value).map(Some) }
+    }
+}
+
+/// Writes the rules as TOML that `Rules::parse` reads back.
+impl Display for Rules {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let optional = |rule: Option<String>| rule.unwrap_or_else(|| "none".to_string());
+        writeln!(f, "topology = \"{}\"", self.topology)?;
+        writeln!(f, "steering = \"{}\"", self.steering)?;
+        writeln!(f, "wall = \"{}\"", self.wall)?;
+        writeln!(f, "trail = \"{}\"", self.trail)?;
+        writeln!(f, "head_on = \"{}\"", self.head_on)?;
+        writeln!(f, "trails = \"{}\"", self.trails)?;
+        writeln!(f, "friendly_fire = {}", self.friendly_fire)?;
+        writeln!(f, "boost_moves = {}", self.boost_moves)?;
+        match self.max_armor {
+            Some(armor) => { writeln!(f, "max_armor = {}", armor)? }
+            None => { writeln!(f, "max_armor = \"none\"")? }
+        }
+        writeln!(f, "blast = \"{}\"", self.blast)?;
+        writeln!(f, "inventory_slots = {}", self.inventory_slots)?;
+        writeln!(f, "sudden_death = \"{}\"", optional(self.sudden_death.map(|rule| rule.to_string())))?;
+        writeln!(f, "turn_limit = \"{}\"", optional(self.turn_limit.map(|limit| limit.to_string())))?;
+        writeln!(f, "visibility = \"{}\"", self.visibility)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip_through_toml() {
+        for name in PRESETS {
+            let rules = Rules::preset(name).unwrap();
+            assert_eq!(Rules::parse(&rules.to_string()).unwrap(), rules, "{}", name);
+        }
+    }
+
+    #[test]
+    fn every_rule_round_trips_through_toml() {
+        let rules = Rules {
+            topology: Topology::Cylinder,
+            steering: Steering::Relative,
+            trails: TrailPersistence::Fading { turns: 6 },
+            friendly_fire: false,
+            boost_moves: 3,
+            max_armor: Some(2),
+            blast: "plus:3:damage:chain".parse().unwrap(),
+            inventory_slots: 2,
+            sudden_death: Some("40:5".parse().unwrap()),
+            turn_limit: Some("200:territory".parse().unwrap()),
+            visibility: Visibility::Sight { radius: 4 },
+            ..Rules::default()
+        };
+        assert_eq!(Rules::parse(&rules.to_string()).unwrap(), rules);
+    }
+
+    #[test]
+    fn keys_left_out_keep_the_preset() {
+        let rules = Rules::parse("preset = \"classic\"\nboost_moves = 5\n").unwrap();
+        assert_eq!(rules, Rules { boost_moves: 5, ..Rules::preset("classic").unwrap() });
+        assert!(Rules::parse("walls = \"kill\"\n").is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use crate::player::Action;
+
+/// Progress of a controller's search, sent whenever it finds a better move and once more when
+/// it stops. Subscribe with `Game::subscribe_search_info`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchInfo {
+    pub player_id: usize,
+    /// Our moves along the deepest line expanded so far.
+    pub depth: usize,
+    /// Positions scored, over all search threads.
+    pub nodes: u64,
+    pub nodes_per_second: f64,
+    pub elapsed: Duration,
+    /// Our moves along the best line, starting with the move the search would play.
+    pub best_line: Vec<Action>,
+    /// Worst-case score of `best_line`.
+    pub score: i32,
+    /// Share of scored positions found in the transposition table.
+    pub cache_hit_rate: f64,
+    /// Searching the predicted position while the opponent thinks, rather than for a move.
+    pub pondering: bool,
+    /// The last report of this search.
+    pub done: bool,
+}
+
+impl Display for SearchInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "player {} {}depth {} nodes {} nps {:.0} time {}ms score {} hits {:.1}% line {:?}",
+               self.player_id, if self.pondering { "ponder " } else { "" }, self.depth, self.nodes,
+               self.nodes_per_second, self.elapsed.as_millis(), self.score, self.cache_hit_rate * 100.0,
+               self.best_line)
+    }
+}
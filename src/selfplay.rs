@@ -0,0 +1,282 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::game::{Game, GameState};
+use crate::npy::write_npy;
+use crate::observation::{encode, Observation};
+use crate::player_controller::{parse_controller, random_safe_action, ExternalController, PlayerController};
+use crate::grid::Grid;
+use crate::worker::ThreadedController;
+use crate::clock::{OverrunPenalty, TimeControl};
+use crate::map_generator::{generate, MapConfig, Symmetry};
+use crate::rules::Rules;
+
+#[derive(Debug, Clone)]
+pub struct SelfPlayConfig {
+    /// One controller spec per player, see `parse_controller`.
+    pub controllers: Vec<String>,
+    /// Each player's team, see `Board::set_teams`. Empty puts every player on a team of their own.
+    pub teams: Vec<usize>,
+    pub games: usize,
+    pub seed: u64,
+    pub height: usize,
+    pub width: usize,
+    pub grid: Grid,
+    pub obstacle_density: f64,
+    pub symmetry: Symmetry,
+    /// Topology, collisions, power-up behavior and how the game ends, see `Rules`.
+    pub rules: Rules,
+    /// Clocks enforced by `Game`; controllers are told their remaining time each turn.
+    pub time_control: Option<TimeControl>,
+    pub overrun_penalty: OverrunPenalty,
+    /// Runs each controller on a worker thread, falling back to a random safe move when it
+    /// takes longer than this.
+    pub controller_deadline: Option<Duration>,
+    /// Power-ups placed before symmetry, so a symmetric map can hold more.
+    pub power_ups: usize,
+    /// Rounds at the start of each game played with random safe moves. These are not recorded.
+    pub opening_moves: usize,
+    pub shard_size: usize,
+    pub output: PathBuf,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        SelfPlayConfig {
+            controllers: vec!["bfs:100".to_string(), "bfs:100".to_string()],
+            teams: Vec::new(),
+            games: 100,
+            seed: 0,
+            height: 10,
+            width: 10,
+            grid: Grid::Square,
+            obstacle_density: 0.0,
+            symmetry: Symmetry::Point,
+            rules: Rules::default(),
+            time_control: None,
+            overrun_penalty: OverrunPenalty::Forfeit,
+            controller_deadline: None,
+            power_ups: 3,
+            opening_moves: 2,
+            shard_size: 10000,
+            output: PathBuf::from("selfplay"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SelfPlaySummary {
+    pub games: usize,
+    pub positions: usize,
+    pub shards: usize,
+    /// Wins per team.
+    pub wins: Vec<usize>,
+    pub draws: usize,
+}
+
+struct Record {
+    observation: Observation,
+    player_id: usize,
+    /// Index into `Board::actions`.
+    action: u8,
+    score: Option<i32>,
+    outcome: i8,
+}
+
+const TEAMS: &str = "teams must name one team per controller, numbered from 0";
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// Plays `config.games` games and writes every recorded position to `config.output` as shards
+/// of `.npy` files: `shard_NNNNN_observations.npy` (positions, planes, height, width),
+/// `_actions.npy` (index into `Board::actions`), `_scores.npy` (the mover's search score, NaN if it
+/// reports none), `_outcomes.npy` (+1 if the mover's team went on to win, -1 if it lost, 0 for a draw) and
+/// `_player_ids.npy`.
+pub fn run(config: &SelfPlayConfig) -> io::Result<SelfPlaySummary> {
+    for spec in &config.controllers {
+        if parse_controller(spec).is_err() {
+            return Err(invalid(&format!("unknown controller spec: {}", spec)))
+        }
+    }
+    if !(2..=4).contains(&config.controllers.len()) {
+        return Err(invalid("self-play boards seat two to four controllers"))
+    }
+    let n_players = config.controllers.len();
+    if !config.teams.is_empty() && (config.teams.len() != n_players || config.teams.iter().any(|team| *team >= n_players)) {
+        return Err(invalid(TEAMS))
+    }
+    std::fs::create_dir_all(&config.output)?;
+
+    let mut summary = SelfPlaySummary { wins: vec![0; config.controllers.len()], ..Default::default() };
+    let mut pending = Vec::new();
+    for game_index in 0..config.games {
+        let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(game_index as u64));
+        let (records, winning_team) = play_game(config, &mut rng)?;
+        match winning_team {
+            Some(team) => { summary.wins[team] += 1 }
+            None => { summary.draws += 1 }
+        }
+        summary.games += 1;
+        summary.positions += records.len();
+        pending.extend(records);
+
+        while pending.len() >= config.shard_size.max(1) {
+            let rest = pending.split_off(config.shard_size.max(1));
+            write_shard(config, summary.shards, &pending)?;
+            summary.shards += 1;
+            pending = rest;
+        }
+    }
+    if !pending.is_empty() {
+        write_shard(config, summary.shards, &pending)?;
+        summary.shards += 1;
+    }
+    Ok(summary)
+}
+
+/// Plays a single game with `config`'s controllers and board settings, returning the winning
+/// team, or `None` for a draw.
+pub fn play_match(config: &SelfPlayConfig, rng: &mut StdRng) -> io::Result<Option<usize>> {
+    let (_, winning_team) = play_game(config, rng)?;
+    Ok(winning_team)
+}
+
+fn play_game(config: &SelfPlayConfig, rng: &mut StdRng) -> io::Result<(Vec<Record>, Option<usize>)> {
+    let map = MapConfig {
+        height: config.height,
+        width: config.width,
+        grid: config.grid,
+        obstacle_density: config.obstacle_density,
+        symmetry: config.symmetry,
+        power_up_clusters: config.power_ups,
+        cluster_size: 1,
+        rules: config.rules,
+        players: config.controllers.len(),
+    };
+    let mut board = generate(&map, rng).map_err(|_| invalid("no arena fits these settings"))?;
+    if !config.teams.is_empty() {
+        board.set_teams(&config.teams).map_err(|_| invalid(TEAMS))?;
+    }
+    let mut controllers = Vec::new();
+    let mut seats: Vec<Box<dyn PlayerController>> = Vec::new();
+    for spec in &config.controllers {
+        let controller = parse_controller(spec).map_err(|_| invalid(&format!("unknown controller spec: {}", spec)))?;
+        controllers.push(match config.controller_deadline {
+            Some(deadline) => { Box::new(ThreadedController::new(controller, deadline)) }
+            None => { controller }
+        });
+        seats.push(Box::new(ExternalController {}));
+    }
+    let n_players = controllers.len();
+    let mut game = Game::new(board, seats).map_err(|_| invalid("need exactly one controller per player"))?;
+    game.set_time_control(config.time_control, config.overrun_penalty);
+
+    let mut records = Vec::new();
+    while let GameState::Active { turn, .. } = game.state() {
+        let opening = *turn < config.opening_moves * n_players;
+        let active_id = turn % n_players;
+        if let (Some(time_control), Some(remaining)) = (config.time_control, game.remaining_time(active_id)) {
+            controllers[active_id].set_clock(remaining, time_control);
+        }
+        let start = records.len();
+        let result = game.run_turn_with(|board, player_id| {
+            if opening {
+                return random_safe_action(board, player_id, rng)
+            }
+            let controller = &mut controllers[player_id];
+            let action = controller.get_action(board, player_id);
+            records.push(Record {
+                observation: encode(board, player_id),
+                player_id,
+                action: 0,
+                score: controller.search_score(),
+                outcome: 0,
+            });
+            action
+        });
+        match result {
+            // Record the moves played, which a clock overrun may have replaced, and drop any the
+            // game never got to.
+            Ok((_, played)) => {
+                records.truncate(start + played.len());
+                let legal = game.board().actions();
+                for (record, action) in records[start..].iter_mut().zip(played) {
+                    record.action = legal.iter().position(|legal| *legal == action).unwrap_or(0) as u8;
+                }
+            }
+            Err(_) => {
+                records.truncate(start);
+                game.forfeit(active_id).map_err(|_| invalid(&format!("player {} could not forfeit", active_id)))?;
+            }
+        }
+    }
+
+    let winning_team = match game.state() {
+        GameState::Over { winning_team, .. } => { *winning_team }
+        GameState::Active { .. } => { None }
+    };
+    for record in &mut records {
+        record.outcome = match winning_team {
+            Some(team) if team == game.board().team(record.player_id) => { 1 }
+            Some(_) => { -1 }
+            None => { 0 }
+        };
+    }
+    Ok((records, winning_team))
+}
+
+fn write_shard(config: &SelfPlayConfig, shard: usize, records: &[Record]) -> io::Result<()> {
+    let path = |name: &str| config.output.join(format!("shard_{:05}_{}.npy", shard, name));
+    let first = &records[0].observation;
+    let mut observations = Vec::with_capacity(records.len() * first.data.len());
+    let mut actions = Vec::new();
+    let mut scores = Vec::new();
+    let mut outcomes = Vec::new();
+    let mut player_ids = Vec::new();
+    for record in records {
+        observations.extend_from_slice(&record.observation.data);
+        actions.push(record.action);
+        scores.push(match record.score {
+            Some(score) => { score as f32 }
+            None => { f32::NAN }
+        });
+        outcomes.push(record.outcome);
+        player_ids.push(record.player_id as u8);
+    }
+    write_npy(&path("observations"), &[records.len(), first.planes, first.height, first.width], &observations)?;
+    write_npy(&path("actions"), &[records.len()], &actions)?;
+    write_npy(&path("scores"), &[records.len()], &scores)?;
+    write_npy(&path("outcomes"), &[records.len()], &outcomes)?;
+    write_npy(&path("player_ids"), &[records.len()], &player_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Topology;
+
+    #[test]
+    fn moves_lost_to_the_clock_are_not_recorded() {
+        let config = SelfPlayConfig {
+            controllers: vec!["bfs:1".to_string(), "bfs:1".to_string()],
+            time_control: Some(TimeControl::PerMove { limit: Duration::ZERO }),
+            opening_moves: 0,
+            ..Default::default()
+        };
+        let (records, winning_team) = play_game(&config, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_eq!(winning_team, Some(1));
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn games_that_cannot_start_say_why() {
+        let mut config = SelfPlayConfig { grid: Grid::Hex, height: 9, ..Default::default() };
+        config.rules.topology = Topology::Torus;
+        let error = play_match(&config, &mut StdRng::seed_from_u64(0)).unwrap_err();
+        assert_eq!(error.to_string(), "no arena fits these settings");
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::position::Position;
+
+/// Shrinks the arena late in the game: from `start_turn` on, the outermost open ring of cells
+/// turns into walls every `interval` turns.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct SuddenDeath {
+    pub start_turn: usize,
+    pub interval: usize,
+}
+
+impl SuddenDeath {
+    /// How many rings have closed once the game reaches `turn`.
+    pub fn rings_closed(&self, turn: usize) -> usize {
+        if turn < self.start_turn {
+            0
+        } else {
+            (turn - self.start_turn) / self.interval.max(1) + 1
+        }
+    }
+
+    /// The turn `ring` closes on, counting the outer ring as 0.
+    pub fn closing_turn(&self, ring: usize) -> usize {
+        self.start_turn + ring * self.interval.max(1)
+    }
+}
+
+/// Which ring of a `height` x `width` board `position` lies on, counting the outer ring as 0.
+pub fn ring(position: Position, height: usize, width: usize) -> usize {
+    position.row
+        .min(position.column)
+        .min(height - 1 - position.row)
+        .min(width - 1 - position.column)
+}
+
+/// Parses `<start_turn>:<interval>`.
+impl FromStr for SuddenDeath {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start_turn, interval) = s.split_once(':').ok_or(())?;
+        let interval = interval.parse().map_err(|_| ())?;
+        if interval == 0 {
+            return Err(())
+        }
+        Ok(SuddenDeath {
+            start_turn: start_turn.parse().map_err(|_| ())?,
+            interval,
+        })
+    }
+}
+
+impl Display for SuddenDeath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.start_turn, self.interval)
+    }
+}
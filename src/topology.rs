@@ -0,0 +1,98 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::position::Position;
+
+/// How the edges of the board connect.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Topology {
+    /// Walled rectangle: moving off an edge is not possible.
+    #[default]
+    Bounded,
+    /// Both axes wrap around.
+    Torus,
+    /// Columns wrap around, so the left and right edges meet; rows are bounded.
+    Cylinder,
+}
+
+#[allow(clippy::result_unit_err)]
+impl Topology {
+    /// Offsets `position` on a `height` x `width` board, wrapping the axes this topology joins.
+    pub fn offset(&self, position: Position, row_offset: isize, col_offset: isize,
+                  height: usize, width: usize) -> Result<Position, ()> {
+        let row = wrap(position.row, row_offset, height, self.wraps_rows())?;
+        let column = wrap(position.column, col_offset, width, self.wraps_columns())?;
+        Ok(Position::new(row, column))
+    }
+
+    /// Whether the top and bottom edges meet.
+    pub fn wraps_rows(&self) -> bool {
+        *self == Topology::Torus
+    }
+
+    /// Whether the left and right edges meet.
+    pub fn wraps_columns(&self) -> bool {
+        matches!(self, Topology::Torus | Topology::Cylinder)
+    }
+}
+
+fn wrap(value: usize, offset: isize, size: usize, wraps: bool) -> Result<usize, ()> {
+    let new_value = value as isize + offset;
+    if wraps {
+        Ok(new_value.rem_euclid(size as isize) as usize)
+    } else if new_value < 0 || new_value >= size as isize {
+        Err(())
+    } else {
+        Ok(new_value as usize)
+    }
+}
+
+impl FromStr for Topology {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounded" => { Ok(Topology::Bounded) }
+            "torus" => { Ok(Topology::Torus) }
+            "cylinder" => { Ok(Topology::Cylinder) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Topology {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Topology::Bounded => { write!(f, "bounded") }
+            Topology::Torus => { write!(f, "torus") }
+            Topology::Cylinder => { write!(f, "cylinder") }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torus_wraps_both_axes() {
+        let corner = Position::new(0, 0);
+        assert_eq!(Topology::Torus.offset(corner, -1, -1, 4, 5), Ok(Position::new(3, 4)));
+        assert_eq!(Topology::Torus.offset(Position::new(3, 4), 1, 1, 4, 5), Ok(corner));
+    }
+
+    #[test]
+    fn cylinder_wraps_only_columns() {
+        let corner = Position::new(0, 0);
+        assert_eq!(Topology::Cylinder.offset(corner, 0, -1, 4, 5), Ok(Position::new(0, 4)));
+        assert_eq!(Topology::Cylinder.offset(corner, -1, 0, 4, 5), Err(()));
+        assert_eq!(Topology::Cylinder.offset(Position::new(3, 0), 1, 0, 4, 5), Err(()));
+    }
+
+    #[test]
+    fn bounded_stops_at_the_edges() {
+        let corner = Position::new(3, 4);
+        assert_eq!(Topology::Bounded.offset(corner, 0, 1, 4, 5), Err(()));
+        assert_eq!(Topology::Bounded.offset(corner, 1, 0, 4, 5), Err(()));
+        assert_eq!(Topology::Bounded.offset(corner, -1, -1, 4, 5), Ok(Position::new(2, 3)));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Scores already computed, keyed by a hash of the whole position, shared between search
+/// threads without locks. Each slot stores the score next to the key XORed with it, so a slot
+/// torn by two threads writing at once reads back as a miss rather than a wrong score. A new
+/// entry replaces whatever held its slot.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+#[derive(Default)]
+struct Slot {
+    /// The entry's key XORed with `data`.
+    check: AtomicU64,
+    /// The score in the low 32 bits, with `OCCUPIED` set.
+    data: AtomicU64,
+}
+
+const OCCUPIED: u64 = 1 << 32;
+
+/// Slots in a table from `TranspositionTable::new`, 4 MiB worth.
+pub const DEFAULT_SLOTS: usize = 1 << 18;
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::with_slots(DEFAULT_SLOTS)
+    }
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        TranspositionTable::default()
+    }
+
+    /// A table of at least `slots` slots, rounded up to a power of two.
+    pub fn with_slots(slots: usize) -> Self {
+        TranspositionTable { slots: (0..slots.max(1).next_power_of_two()).map(|_| Slot::default()).collect() }
+    }
+
+    /// The key for `state`, which should cover everything the score depends on.
+    pub fn key<T: Hash>(state: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & (self.slots.len() - 1)]
+    }
+
+    pub fn get(&self, key: u64) -> Option<i32> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+        if data & OCCUPIED != 0 && check ^ data == key {
+            Some(data as u32 as i32)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&self, key: u64, score: i32) {
+        let slot = self.slot(key);
+        let data = OCCUPIED | score as u32 as u64;
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// Occupied slots.
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.data.load(Ordering::Relaxed) & OCCUPIED != 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.data.store(0, Ordering::Relaxed);
+            slot.check.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::player::Action;
+    use crate::position::Position;
+    use crate::power_up::PowerUp;
+
+    #[test]
+    fn keys_tell_held_power_ups_apart() {
+        let mut board = Board::new_default(5, 5).unwrap();
+        board.set_inventory_slots(1);
+        board.place_power_up(Position::new(0, 1), PowerUp::Armor).unwrap();
+        let holding = board.apply_action(0, Action::Right).unwrap();
+        let used = holding.apply_action(0, Action::Use).unwrap();
+        let mut plain = Board::new_default(5, 5).unwrap();
+        plain.set_inventory_slots(1);
+        let plain = plain.apply_action(0, Action::Right).unwrap();
+        assert_eq!(holding.to_string(), plain.to_string());
+        assert_ne!(TranspositionTable::key(&(0, &holding)), TranspositionTable::key(&(0, &plain)));
+        assert_ne!(TranspositionTable::key(&(0, &used)), TranspositionTable::key(&(0, &holding)));
+    }
+
+    #[test]
+    fn stores_and_replaces_scores() {
+        let table = TranspositionTable::with_slots(4);
+        table.insert(1, -7);
+        table.insert(2, i32::MIN);
+        assert_eq!(table.get(1), Some(-7));
+        assert_eq!(table.get(2), Some(i32::MIN));
+        assert_eq!(table.get(3), None);
+        // 5 shares a slot with 1 and takes it over.
+        table.insert(5, 9);
+        assert_eq!((table.get(5), table.get(1)), (Some(9), None));
+        assert_eq!(table.len(), 2);
+        table.clear();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn torn_slots_read_as_misses() {
+        let table = TranspositionTable::with_slots(1);
+        table.insert(1, 10);
+        // Another thread got as far as writing its data for key 2 over ours.
+        table.slots[0].data.store(OCCUPIED | 20, Ordering::Relaxed);
+        assert_eq!(table.get(1), None);
+        assert_eq!(table.get(2), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::info;
+use crate::player_controller::ZoneWeights;
+use crate::selfplay::{play_match, SelfPlayConfig};
+use crate::grid::Grid;
+use crate::clock::{OverrunPenalty, TimeControl};
+use crate::map_generator::Symmetry;
+use crate::rules::Rules;
+
+/// Each parameter is searched within this range.
+const PARAMETER_RANGE: (f64, f64) = (0.0, 100.0);
+
+#[derive(Debug, Clone)]
+pub struct TuneConfig {
+    pub population: usize,
+    pub generations: usize,
+    /// Games each candidate plays against `opponent` per generation, alternating seats.
+    pub games: usize,
+    pub turn_time_milliseconds: u128,
+    pub opponent: String,
+    pub seed: u64,
+    /// Standard deviation of the Gaussian mutation applied to every child parameter.
+    pub mutation: f64,
+    /// Best candidates copied unchanged into the next generation.
+    pub elite: usize,
+    pub threads: usize,
+    pub height: usize,
+    pub width: usize,
+    pub grid: Grid,
+    pub obstacle_density: f64,
+    pub symmetry: Symmetry,
+    pub rules: Rules,
+    pub time_control: Option<TimeControl>,
+    pub overrun_penalty: OverrunPenalty,
+    pub controller_deadline: Option<Duration>,
+    pub power_ups: usize,
+    pub opening_moves: usize,
+    pub output: PathBuf,
+    /// A checkpoint written by an earlier run to continue from.
+    pub resume: Option<PathBuf>,
+}
+
+impl Default for TuneConfig {
+    fn default() -> Self {
+        TuneConfig {
+            population: 16,
+            generations: 10,
+            games: 8,
+            turn_time_milliseconds: 20,
+            opponent: "bfs:20".to_string(),
+            seed: 0,
+            mutation: 5.0,
+            elite: 2,
+            threads: 4,
+            height: 10,
+            width: 10,
+            grid: Grid::Square,
+            obstacle_density: 0.0,
+            symmetry: Symmetry::Point,
+            rules: Rules::default(),
+            time_control: None,
+            overrun_penalty: OverrunPenalty::Forfeit,
+            controller_deadline: None,
+            power_ups: 3,
+            opening_moves: 2,
+            output: PathBuf::from("tune"),
+            resume: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// `ZoneWeights` in `to_vec` order.
+    pub parameters: Vec<f64>,
+    /// Fraction of games won in the generation it was last evaluated.
+    pub fitness: f64,
+}
+
+/// The `BFSController` spec for `parameters`, as accepted by `parse_controller`.
+pub fn candidate_spec(turn_time_milliseconds: u128, parameters: &[f64]) -> String {
+    let weights: Vec<String> = parameters.iter().map(|parameter| (parameter.round() as i32).to_string()).collect();
+    format!("bfs:{}:{}", turn_time_milliseconds, weights.join(","))
+}
+
+/// Evolves `ZoneWeights` with a genetic algorithm: candidates are ranked by win rate against
+/// `config.opponent`, then the next generation keeps the elite and fills up with mutated blends
+/// of tournament-selected parents. Every generation is checkpointed to
+/// `config.output/generation_NNNN.txt` and the best spec so far to `best.txt`. Resuming from a
+/// checkpoint breeds the generation after it, as an uninterrupted run would have.
+pub fn run(config: &TuneConfig) -> io::Result<Candidate> {
+    std::fs::create_dir_all(&config.output)?;
+    let (mut population, mut best, mut rng, first_generation) = match &config.resume {
+        Some(path) => {
+            let checkpoint = load_checkpoint(path)?;
+            let mut rng = StdRng::seed_from_u64(checkpoint.rng_seed);
+            let population = next_generation(config, &checkpoint.ranked, &mut rng);
+            (population, Some(checkpoint.best), rng, checkpoint.generation + 1)
+        }
+        None => {
+            let mut rng = StdRng::seed_from_u64(config.seed);
+            (initial_population(config.population, &mut rng), None, rng, 0)
+        }
+    };
+    if population.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "population is empty"))
+    }
+
+    for generation in first_generation..first_generation + config.generations {
+        let fitness = evaluate_population(config, generation, &population);
+        let mut ranked: Vec<Candidate> = population.into_iter().zip(fitness)
+            .map(|(parameters, fitness)| Candidate { parameters, fitness })
+            .collect();
+        ranked.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        info!(generation, best = candidate_spec(config.turn_time_milliseconds, &ranked[0].parameters),
+              fitness = ranked[0].fitness, "generation ranked");
+
+        if best.as_ref().is_none_or(|best| ranked[0].fitness >= best.fitness) {
+            best = Some(ranked[0].clone());
+            std::fs::write(config.output.join("best.txt"),
+                           candidate_spec(config.turn_time_milliseconds, &ranked[0].parameters) + "\n")?;
+        }
+        // Reseed from a checkpointed value so a resumed run breeds exactly what this one would.
+        let rng_seed: u64 = rng.gen();
+        rng = StdRng::seed_from_u64(rng_seed);
+        let checkpoint = Checkpoint { generation, rng_seed, best: best.clone().unwrap(), ranked };
+        write_checkpoint(config, &checkpoint)?;
+        population = next_generation(config, &checkpoint.ranked, &mut rng);
+    }
+    best.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no generations were run"))
+}
+
+fn initial_population(size: usize, rng: &mut StdRng) -> Vec<Vec<f64>> {
+    // Start from the hand-picked weights, scaled up so mutations have room to work.
+    let defaults: Vec<f64> = ZoneWeights::default().to_vec().iter().map(|weight| *weight as f64 * 10.0).collect();
+    let mut population = vec![defaults.clone()];
+    while population.len() < size {
+        population.push(defaults.iter().map(|_| rng.gen_range(PARAMETER_RANGE.0..PARAMETER_RANGE.1)).collect());
+    }
+    population
+}
+
+fn evaluate_population(config: &TuneConfig, generation: usize, population: &[Vec<f64>]) -> Vec<f64> {
+    let chunk_size = population.len().div_ceil(config.threads.max(1));
+    thread::scope(|scope| {
+        let handles: Vec<_> = population.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                chunk.iter().map(|parameters| evaluate(config, generation, parameters)).collect::<Vec<f64>>()
+            }))
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Every candidate in a generation plays the same seeds, so their win rates are comparable.
+/// Draws count as half a win.
+fn evaluate(config: &TuneConfig, generation: usize, parameters: &[f64]) -> f64 {
+    let spec = candidate_spec(config.turn_time_milliseconds, parameters);
+    let mut wins = 0.0;
+    for game in 0..config.games {
+        let seat = game % 2;
+        let mut controllers = vec![config.opponent.clone(), config.opponent.clone()];
+        controllers[seat] = spec.clone();
+        let match_config = SelfPlayConfig {
+            controllers,
+            height: config.height,
+            width: config.width,
+            grid: config.grid,
+            obstacle_density: config.obstacle_density,
+            symmetry: config.symmetry,
+            rules: config.rules,
+            time_control: config.time_control,
+            overrun_penalty: config.overrun_penalty,
+            controller_deadline: config.controller_deadline,
+            power_ups: config.power_ups,
+            opening_moves: config.opening_moves,
+            ..Default::default()
+        };
+        let seed = config.seed ^ ((generation as u64) << 32) ^ game as u64;
+        match play_match(&match_config, &mut StdRng::seed_from_u64(seed)) {
+            Ok(Some(winner_id)) if winner_id == seat => { wins += 1.0 }
+            Ok(None) => { wins += 0.5 }
+            _ => {}
+        }
+    }
+    wins / config.games.max(1) as f64
+}
+
+fn next_generation(config: &TuneConfig, ranked: &[Candidate], rng: &mut StdRng) -> Vec<Vec<f64>> {
+    let mut population: Vec<Vec<f64>> = ranked.iter().take(config.elite).map(|candidate| candidate.parameters.clone()).collect();
+    while population.len() < ranked.len() {
+        let a = &tournament(ranked, rng).parameters;
+        let b = &tournament(ranked, rng).parameters;
+        let child = a.iter().zip(b)
+            .map(|(a, b)| {
+                let blend = rng.gen_range(0.0..1.0);
+                let value = a * blend + b * (1.0 - blend) + gaussian(rng) * config.mutation;
+                value.clamp(PARAMETER_RANGE.0, PARAMETER_RANGE.1)
+            })
+            .collect();
+        population.push(child);
+    }
+    population
+}
+
+fn tournament<'a>(ranked: &'a [Candidate], rng: &mut StdRng) -> &'a Candidate {
+    let mut best = &ranked[rng.gen_range(0..ranked.len())];
+    for _ in 1..3 {
+        let other = &ranked[rng.gen_range(0..ranked.len())];
+        if other.fitness > best.fitness {
+            best = other;
+        }
+    }
+    best
+}
+
+/// A standard normal sample, by the Box-Muller transform.
+fn gaussian(rng: &mut StdRng) -> f64 {
+    let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let v: f64 = rng.gen_range(0.0..1.0);
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+/// A ranked generation together with what is needed to breed the next one.
+#[derive(Debug, Clone, PartialEq)]
+struct Checkpoint {
+    generation: usize,
+    /// Seed of the rng that breeds the next generation.
+    rng_seed: u64,
+    /// The best candidate of this and all earlier generations.
+    best: Candidate,
+    /// This generation, best first.
+    ranked: Vec<Candidate>,
+}
+
+fn format_candidate(candidate: &Candidate) -> String {
+    let parameters: Vec<String> = candidate.parameters.iter().map(|parameter| parameter.to_string()).collect();
+    format!("{} {}", candidate.fitness, parameters.join(","))
+}
+
+fn parse_candidate(line: &str) -> Option<Candidate> {
+    let (fitness, parameters) = line.split_once(' ')?;
+    let parameters: Result<Vec<f64>, _> = parameters.split(',').map(|parameter| parameter.parse()).collect();
+    Some(Candidate { parameters: parameters.ok()?, fitness: fitness.parse().ok()? })
+}
+
+/// Checkpoints start with `# generation N`, `# rng SEED` and `# best fitness p1,p2,...` lines,
+/// followed by one `fitness p1,p2,...` line per candidate, best first.
+fn write_checkpoint(config: &TuneConfig, checkpoint: &Checkpoint) -> io::Result<()> {
+    let path = config.output.join(format!("generation_{:04}.txt", checkpoint.generation));
+    std::fs::write(path, format_checkpoint(checkpoint))
+}
+
+fn format_checkpoint(checkpoint: &Checkpoint) -> String {
+    let mut text = format!("# generation {}\n# rng {}\n# best {}\n",
+                           checkpoint.generation, checkpoint.rng_seed, format_candidate(&checkpoint.best));
+    for candidate in &checkpoint.ranked {
+        text.push_str(&format_candidate(candidate));
+        text.push('\n');
+    }
+    text
+}
+
+fn load_checkpoint(path: &Path) -> io::Result<Checkpoint> {
+    parse_checkpoint(&std::fs::read_to_string(path)?)
+}
+
+fn parse_checkpoint(text: &str) -> io::Result<Checkpoint> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad checkpoint line: {}", line));
+    let mut lines = text.lines();
+    let mut header = |prefix: &str| {
+        let line = lines.next().unwrap_or("");
+        line.strip_prefix(prefix).map(str::trim).ok_or_else(|| invalid(line))
+    };
+    let generation = header("# generation ")?;
+    let generation: usize = generation.parse().map_err(|_| invalid(generation))?;
+    let rng_seed = header("# rng ")?;
+    let rng_seed: u64 = rng_seed.parse().map_err(|_| invalid(rng_seed))?;
+    let best = header("# best ")?;
+    let best = parse_candidate(best).ok_or_else(|| invalid(best))?;
+    let mut ranked = Vec::new();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        ranked.push(parse_candidate(line).ok_or_else(|| invalid(line))?);
+    }
+    Ok(Checkpoint { generation, rng_seed, best, ranked })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let checkpoint = Checkpoint {
+            generation: 3,
+            rng_seed: u64::MAX - 7,
+            best: Candidate { parameters: vec![12.5, 0.0, 99.75], fitness: 0.875 },
+            ranked: vec![
+                Candidate { parameters: vec![1.0, 2.0, 3.0], fitness: 0.5 },
+                Candidate { parameters: vec![4.25, 5.0, 6.0], fitness: 0.25 },
+            ],
+        };
+        assert_eq!(parse_checkpoint(&format_checkpoint(&checkpoint)).unwrap(), checkpoint);
+        assert!(parse_checkpoint("# generation 3\n0.5 1,2,3\n").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::board::{Board, CellState};
+use crate::player::PlayerState;
+use crate::position::Position;
+use crate::player_controller::{zone_scores, ZoneWeights};
+
+/// How a game that reaches its turn limit is decided between the players still alive.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Tiebreak {
+    #[default]
+    Draw,
+    /// Most cells covered by the player's trail and head.
+    Cells,
+    /// Most open cells reached first, as in the Voronoi evaluator.
+    Territory,
+    Armor,
+}
+
+impl Tiebreak {
+    /// The single team ahead on this tiebreak, adding up its surviving players' scores, or
+    /// `None` for a draw.
+    pub fn winner(&self, board: &Board, alive_ids: &[usize]) -> Option<usize> {
+        let scores: Vec<i32> = match self {
+            Tiebreak::Draw => { return None }
+            Tiebreak::Cells => {
+                let mut cells = vec![0; board.players().len()];
+                for row in 0..board.height() {
+                    for column in 0..board.width() {
+                        let position = Position::new(row, column);
+                        match board.get_cell(position).map(|cell| cell.get_state()) {
+                            Ok(CellState::Owned { player_id }) | Ok(CellState::Occupied { player_id }) => {
+                                cells[player_id] += 1;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                cells
+            }
+            Tiebreak::Territory => {
+                let weights = ZoneWeights::uniform(1);
+                zone_scores(board, &weights)
+            }
+            Tiebreak::Armor => {
+                board.players().iter()
+                    .map(|player| match player.get_state() {
+                        PlayerState::Alive { armor, .. } => { armor as i32 }
+                        PlayerState::Dead => { 0 }
+                    })
+                    .collect()
+            }
+        };
+        let mut team_scores = vec![None; board.players().len()];
+        for id in alive_ids {
+            let team_score = &mut team_scores[board.team(*id)];
+            *team_score = Some(team_score.unwrap_or(0) + scores[*id]);
+        }
+        let best = team_scores.iter().flatten().max()?;
+        let leaders: Vec<usize> = (0..team_scores.len()).filter(|team| team_scores[*team] == Some(*best)).collect();
+        match leaders[..] {
+            [team] => { Some(team) }
+            _ => { None }
+        }
+    }
+}
+
+impl FromStr for Tiebreak {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draw" => { Ok(Tiebreak::Draw) }
+            "cells" => { Ok(Tiebreak::Cells) }
+            "territory" => { Ok(Tiebreak::Territory) }
+            "armor" => { Ok(Tiebreak::Armor) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Tiebreak {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tiebreak::Draw => { write!(f, "draw") }
+            Tiebreak::Cells => { write!(f, "cells") }
+            Tiebreak::Territory => { write!(f, "territory") }
+            Tiebreak::Armor => { write!(f, "armor") }
+        }
+    }
+}
+
+/// Ends the game once `turns` turns have been played, deciding it by `tiebreak`.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct TurnLimit {
+    pub turns: usize,
+    pub tiebreak: Tiebreak,
+}
+
+/// Parses `<turns>` or `<turns>:<tiebreak>`; the tiebreak defaults to a draw.
+impl FromStr for TurnLimit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (turns, tiebreak) = match s.split_once(':') {
+            Some((turns, tiebreak)) => { (turns, tiebreak.parse()?) }
+            None => { (s, Tiebreak::Draw) }
+        };
+        Ok(TurnLimit { turns: turns.parse().map_err(|_| ())?, tiebreak })
+    }
+}
+
+impl Display for TurnLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.turns, self.tiebreak)
+    }
+}
@@ -0,0 +1,393 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::blast::BlastShape;
+use crate::board::{Board, BoardCell, CellState};
+use crate::player::{Player, PlayerState};
+use crate::position::Position;
+
+/// How much of the board each player sees. Teammates share what they see.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Visibility {
+    /// Every player sees the whole board.
+    #[default]
+    Full,
+    /// Players see every cell within `radius` of their head, in any direction.
+    Radius {
+        radius: usize
+    },
+    /// As `Radius`, but walls block the view. Lines of sight are traced on rows and columns,
+    /// so they are approximate on hex grids.
+    Sight {
+        radius: usize
+    },
+}
+
+/// Parses `full`, `radius:<radius>` or `sight:<radius>`.
+impl FromStr for Visibility {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "full" => { Ok(Visibility::Full) }
+            Some(("radius", radius)) => { Ok(Visibility::Radius { radius: radius.parse().map_err(|_| ())? }) }
+            Some(("sight", radius)) => { Ok(Visibility::Sight { radius: radius.parse().map_err(|_| ())? }) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for Visibility {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Visibility::Full => { write!(f, "full") }
+            Visibility::Radius { radius } => { write!(f, "radius:{}", radius) }
+            Visibility::Sight { radius } => { write!(f, "sight:{}", radius) }
+        }
+    }
+}
+
+/// What a controller takes cells it has never seen to be.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum UnknownCells {
+    /// Empty, hoping for the best.
+    #[default]
+    Open,
+    /// Walls, planning only through cells known to be open.
+    Blocked,
+}
+
+impl FromStr for UnknownCells {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => { Ok(UnknownCells::Open) }
+            "blocked" => { Ok(UnknownCells::Blocked) }
+            _ => { Err(()) }
+        }
+    }
+}
+
+impl Display for UnknownCells {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnknownCells::Open => { write!(f, "open") }
+            UnknownCells::Blocked => { write!(f, "blocked") }
+        }
+    }
+}
+
+/// The board as one player knows it under the board's `Visibility`. Cells out of view show
+/// what the player last saw there, and opponents out of view are as they were last seen: their
+/// armor, boosts and power-ups as well as where they stood. An opponent whose last spot is in
+/// view again, empty, is guessed to be at the nearest open cell out of view. Opponents' trails
+/// hold only the cells known to be theirs.
+#[derive(Debug, Clone)]
+pub struct PlayerView {
+    player_id: usize,
+    /// The last state seen of each cell, `None` for cells never seen.
+    memory: Vec<Vec<Option<BoardCell>>>,
+    /// Cells in view as of the last `update`.
+    visible: Vec<Vec<bool>>,
+    /// Each player as last seen. Spawns are known to everyone.
+    last_seen: Vec<Player>,
+    /// The known board, with cells never seen as empty.
+    board: Board,
+}
+
+impl PlayerView {
+    pub fn new(board: &Board, player_id: usize) -> Self {
+        let last_seen = board.players().clone();
+        let mut view = PlayerView {
+            player_id,
+            memory: vec![vec![None; board.width()]; board.height()],
+            visible: Vec::new(),
+            last_seen,
+            board: board.clone(),
+        };
+        view.update(board);
+        view
+    }
+
+    pub fn player_id(&self) -> usize {
+        self.player_id
+    }
+
+    /// Looks at `board`, the true board, remembering every cell in view.
+    pub fn update(&mut self, board: &Board) {
+        self.visible = in_view(board, self.player_id);
+        let mut known = board.clone();
+        for row in 0..board.height() {
+            for column in 0..board.width() {
+                let position = Position::new(row, column);
+                if self.visible[row][column] {
+                    self.memory[row][column] = board.get_cell(position).ok().copied();
+                    continue
+                }
+                let _ = match self.memory[row][column] {
+                    Some(cell) => { known.set_cell(position, cell.get_state(), cell.expires()) }
+                    None => { known.set_cell(position, CellState::Empty, None) }
+                };
+            }
+        }
+        for player in board.players() {
+            let id = player.get_id();
+            let in_view = match (player.get_state(), self.last_seen[id].get_state()) {
+                (PlayerState::Alive { position, .. }, _) => { self.visible[position.row][position.column] }
+                // Dying leaves the head behind as trail, seen if the spot they were seen on is.
+                (PlayerState::Dead, PlayerState::Alive { position, .. }) => { self.visible[position.row][position.column] }
+                (PlayerState::Dead, PlayerState::Dead) => { true }
+            };
+            if in_view || board.allies(self.player_id, id) {
+                self.last_seen[id] = *player;
+                continue
+            }
+            let mut remembered = self.last_seen[id];
+            if let PlayerState::Alive { position, .. } = remembered.get_state() {
+                let guess = if self.visible[position.row][position.column] {
+                    self.nearest_hidden(&known, position)
+                } else {
+                    Some(position)
+                };
+                match guess {
+                    Some(guess) => {
+                        let _ = remembered.relocate(guess);
+                        let _ = known.set_cell(guess, CellState::Occupied { player_id: id }, None);
+                    }
+                    // Nowhere left to hide, so they must be gone.
+                    None => { remembered.set_state(PlayerState::Dead) }
+                }
+            }
+            let _ = known.set_player(remembered);
+        }
+        for id in 0..board.players().len() {
+            if !board.allies(self.player_id, id) {
+                known.forget_trail(id);
+            }
+        }
+        self.board = known;
+    }
+
+    /// The known board, taking cells never seen to be empty.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// The known board, taking cells never seen to be as `unknown` says.
+    pub fn assume(&self, unknown: UnknownCells) -> Cow<'_, Board> {
+        match unknown {
+            UnknownCells::Open => { Cow::Borrowed(&self.board) }
+            UnknownCells::Blocked => {
+                let mut board = self.board.clone();
+                for row in 0..board.height() {
+                    for column in 0..board.width() {
+                        let position = Position::new(row, column);
+                        // Opponents guessed to stand on unseen cells keep their spot.
+                        let occupied = matches!(board.get_cell(position).map(|cell| cell.get_state()), Ok(CellState::Occupied { .. }));
+                        if self.memory[row][column].is_none() && !occupied {
+                            let _ = board.set_cell(position, CellState::Wall, None);
+                        }
+                    }
+                }
+                Cow::Owned(board)
+            }
+        }
+    }
+
+    /// Whether `position` is in view now.
+    pub fn is_visible(&self, position: Position) -> bool {
+        self.visible.get(position.row).and_then(|row| row.get(position.column)).copied().unwrap_or(false)
+    }
+
+    /// Whether `position` has ever been seen.
+    pub fn is_known(&self, position: Position) -> bool {
+        self.memory.get(position.row).and_then(|row| row.get(position.column)).is_some_and(|cell| cell.is_some())
+    }
+
+    /// `player_id` as last seen.
+    pub fn last_seen(&self, player_id: usize) -> Option<&Player> {
+        self.last_seen.get(player_id)
+    }
+
+    /// The closest cell to `start` that is out of view and not known to be blocked.
+    fn nearest_hidden(&self, known: &Board, start: Position) -> Option<Position> {
+        let mut seen = vec![vec![false; known.width()]; known.height()];
+        let mut queue = VecDeque::from([start]);
+        seen[start.row][start.column] = true;
+        while let Some(position) = queue.pop_front() {
+            let open = matches!(known.get_cell(position).map(|cell| cell.get_state()),
+                                Ok(CellState::Empty) | Ok(CellState::PowerUp { .. }));
+            if open && !self.visible[position.row][position.column] {
+                return Some(position)
+            }
+            for neighbor in known.neighbors(position) {
+                if !seen[neighbor.row][neighbor.column] {
+                    seen[neighbor.row][neighbor.column] = true;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The cells `player_id` and their teammates see on `board`.
+fn in_view(board: &Board, player_id: usize) -> Vec<Vec<bool>> {
+    let (radius, walls_block) = match board.rules().visibility {
+        Visibility::Full => { return vec![vec![true; board.width()]; board.height()] }
+        Visibility::Radius { radius } => { (radius, false) }
+        Visibility::Sight { radius } => { (radius, true) }
+    };
+    let mut visible = vec![vec![false; board.width()]; board.height()];
+    for player in board.players() {
+        if !board.allies(player_id, player.get_id()) {
+            continue
+        }
+        let head = match player.get_state() {
+            PlayerState::Alive { position, .. } => { position }
+            PlayerState::Dead => { continue }
+        };
+        visible[head.row][head.column] = true;
+        for (row_offset, col_offset) in board.grid().blast_offsets(head, BlastShape::Square, radius) {
+            if walls_block && !line_of_sight(board, head, row_offset, col_offset) {
+                continue
+            }
+            if let Ok(position) = board.offset(head, row_offset, col_offset) {
+                visible[position.row][position.column] = true;
+            }
+        }
+    }
+    visible
+}
+
+/// Whether no wall stands strictly between `head` and the cell at the given offset.
+fn line_of_sight(board: &Board, head: Position, row_offset: isize, col_offset: isize) -> bool {
+    let steps = row_offset.abs().max(col_offset.abs());
+    (1..steps).all(|step| {
+        let fraction = step as f64 / steps as f64;
+        let row = (row_offset as f64 * fraction).round() as isize;
+        let column = (col_offset as f64 * fraction).round() as isize;
+        let state = board.offset(head, row, column).and_then(|position| board.get_cell(position).map(|cell| cell.get_state()));
+        state != Ok(CellState::Wall)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation::{encode, OPPONENT_ARMOR};
+    use crate::player::Action;
+    use crate::power_up::PowerUp;
+
+    fn fogged(height: usize, width: usize, spawns: &[Position], radius: usize) -> Board {
+        let mut board = Board::with_spawns(height, width, spawns).unwrap();
+        let mut rules = board.rules();
+        rules.visibility = Visibility::Radius { radius };
+        board.set_rules(rules).unwrap();
+        board
+    }
+
+    #[test]
+    fn hides_stats_picked_up_out_of_view() {
+        let mut board = fogged(8, 8, &[Position::new(0, 0), Position::new(7, 7)], 1);
+        board.place_power_up(Position::new(7, 6), PowerUp::Armor).unwrap();
+        let mut view = PlayerView::new(&board, 0);
+        let board = board.apply_action(1, Action::Left).unwrap();
+        assert!(matches!(board.players()[1].get_state(), PlayerState::Alive { armor: 1, .. }));
+        view.update(&board);
+
+        assert!(matches!(view.board().players()[1].get_state(), PlayerState::Alive { armor: 0, .. }));
+        assert_eq!(encode(view.board(), 0).get(OPPONENT_ARMOR, Position::new(0, 0)), 0.0);
+        assert!(!view.is_known(Position::new(7, 6)));
+    }
+
+    #[test]
+    fn hides_trails_left_out_of_view() {
+        let board = fogged(3, 8, &[Position::new(1, 0), Position::new(1, 7)], 1);
+        let mut straight = PlayerView::new(&board, 0);
+        let mut turning = straight.clone();
+        straight.update(&board.apply_action(1, Action::Left).unwrap().apply_action(1, Action::Left).unwrap());
+        turning.update(&board.apply_action(1, Action::Up).unwrap().apply_action(1, Action::Left).unwrap());
+        assert_eq!(straight.board(), turning.board());
+    }
+
+    #[test]
+    fn shows_what_is_in_view() {
+        let board = fogged(3, 4, &[Position::new(1, 0), Position::new(1, 2)], 2);
+        let board = board.apply_action(1, Action::Up).unwrap();
+        let view = PlayerView::new(&board, 0);
+        assert!(view.is_visible(Position::new(0, 2)));
+        assert!(!view.is_visible(Position::new(0, 3)));
+        assert_eq!(view.board().players()[1], board.players()[1]);
+    }
+
+    #[test]
+    fn moves_opponents_off_a_spot_seen_empty() {
+        let board = fogged(3, 5, &[Position::new(1, 0), Position::new(1, 3)], 1);
+        let mut view = PlayerView::new(&board, 0);
+        let board = board.apply_action(1, Action::Right).unwrap();
+        let board = board.apply_action(0, Action::Right).unwrap();
+        view.update(&board);
+        let board = board.apply_action(0, Action::Right).unwrap();
+        view.update(&board);
+
+        let spawn = Position::new(1, 3);
+        assert!(view.is_visible(spawn));
+        assert_eq!(view.board().get_cell(spawn).unwrap().get_state(), CellState::Owned { player_id: 1 });
+        match view.board().players()[1].get_state() {
+            PlayerState::Alive { position, .. } => {
+                assert_ne!(position, spawn);
+                assert!(!view.is_visible(position));
+                assert_eq!(view.board().get_cell(position).unwrap().get_state(), CellState::Occupied { player_id: 1 });
+            }
+            PlayerState::Dead => { panic!("opponent should still be thought alive") }
+        }
+    }
+
+    #[test]
+    fn remembers_cells_out_of_view() {
+        let mut board = fogged(1, 6, &[Position::new(0, 0), Position::new(0, 5)], 1);
+        board.place_power_up(Position::new(0, 1), PowerUp::Armor).unwrap();
+        let mut view = PlayerView::new(&board, 0);
+        assert!(view.is_known(Position::new(0, 1)));
+        assert!(!view.is_known(Position::new(0, 2)));
+        for _ in 0..3 {
+            board = board.apply_action(0, Action::Right).unwrap();
+            view.update(&board);
+        }
+        assert!(!view.is_visible(Position::new(0, 1)));
+        assert_eq!(view.board().get_cell(Position::new(0, 1)).unwrap().get_state(), CellState::Owned { player_id: 0 });
+        assert_eq!(view.assume(UnknownCells::Blocked).get_cell(Position::new(0, 5)).unwrap().get_state(),
+                   CellState::Occupied { player_id: 1 });
+    }
+
+    #[test]
+    fn walls_block_the_line_of_sight() {
+        let mut board = Board::with_spawns(3, 6, &[Position::new(1, 0), Position::new(0, 5)]).unwrap();
+        board.place_wall(Position::new(1, 1)).unwrap();
+        let mut rules = board.rules();
+        rules.visibility = Visibility::Sight { radius: 3 };
+        board.set_rules(rules).unwrap();
+        let view = PlayerView::new(&board, 0);
+        assert!(view.is_visible(Position::new(1, 1)));
+        assert!(!view.is_visible(Position::new(1, 2)));
+        assert!(view.is_visible(Position::new(0, 2)));
+
+        rules.visibility = Visibility::Radius { radius: 3 };
+        board.set_rules(rules).unwrap();
+        assert!(PlayerView::new(&board, 0).is_visible(Position::new(1, 2)));
+    }
+
+    #[test]
+    fn teammates_share_their_view() {
+        let spawns = [Position::new(0, 0), Position::new(0, 7), Position::new(7, 7), Position::new(7, 0)];
+        let mut board = fogged(8, 8, &spawns, 1);
+        board.set_teams(&[0, 1, 0, 1]).unwrap();
+        let view = PlayerView::new(&board, 0);
+        assert!(view.is_visible(Position::new(6, 6)));
+        assert_eq!(view.board().players()[2], board.players()[2]);
+        assert!(!view.is_visible(Position::new(6, 0)));
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::board::Board;
+use crate::clock::TimeControl;
+use crate::player::Action;
+use crate::player_controller::{random_safe_action, PlayerController};
+use crate::search_info::SearchInfo;
+use crate::visibility::UnknownCells;
+
+/// Shared flag telling a controller to stop searching and answer with what it has.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+enum Request {
+    Clock {
+        remaining: Duration,
+        time_control: TimeControl
+    },
+    Move {
+        id: u64,
+        board: Board,
+        player_id: usize,
+        token: CancelToken
+    },
+    /// Predict the opponent's reply, answer, then ponder until `token` is cancelled.
+    Predict {
+        id: u64,
+        board: Board,
+        player_id: usize,
+        opponent_id: usize,
+        token: CancelToken
+    },
+    PonderHit,
+    PonderMiss,
+    InfoSink(Sender<SearchInfo>),
+}
+
+struct Response {
+    id: u64,
+    /// The move played, or for `Request::Predict` the predicted reply.
+    action: Option<Action>,
+    score: Option<i32>,
+}
+
+/// Runs a controller on its own thread so a slow or stuck `get_action` can't hold up the game.
+/// Each move gets a fresh `CancelToken`, cancelled once `deadline` passes; the move is then
+/// replaced by a random safe one. A controller that never answers keeps its thread, and every
+/// later move falls back the same way.
+///
+/// Between its own moves the controller may ponder: `predict` is answered right away, and the
+/// worker keeps thinking on the predicted position until the game reports a hit or a miss.
+pub struct ThreadedController {
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+    deadline: Duration,
+    /// The deadline for the next move, tightened by the game clock.
+    move_deadline: Duration,
+    next_id: u64,
+    outstanding: Option<CancelToken>,
+    pondering: Option<CancelToken>,
+    last_score: Option<i32>,
+    /// The wrapped controller's `unknown_cells`, asked before it moved to its thread.
+    unknown_cells: UnknownCells,
+    rng: StdRng,
+}
+
+impl ThreadedController {
+    pub fn new(mut controller: Box<dyn PlayerController>, deadline: Duration) -> Self {
+        let unknown_cells = controller.unknown_cells();
+        let (requests, worker_requests) = channel::<Request>();
+        let (worker_responses, responses) = channel();
+        thread::spawn(move || {
+            for request in worker_requests {
+                match request {
+                    Request::Clock { remaining, time_control } => { controller.set_clock(remaining, time_control) }
+                    Request::Move { id, board, player_id, token } => {
+                        controller.set_cancel_token(token);
+                        let action = Some(controller.get_action(&board, player_id));
+                        let score = controller.search_score();
+                        if worker_responses.send(Response { id, action, score }).is_err() {
+                            break
+                        }
+                    }
+                    Request::Predict { id, board, player_id, opponent_id, token } => {
+                        let action = controller.predict(&board, player_id, opponent_id);
+                        if worker_responses.send(Response { id, action, score: None }).is_err() {
+                            break
+                        }
+                        if action.is_some() {
+                            controller.ponder(&token);
+                        }
+                    }
+                    Request::PonderHit => { controller.ponder_hit() }
+                    Request::PonderMiss => { controller.ponder_miss() }
+                    Request::InfoSink(sink) => { controller.set_info_sink(sink) }
+                }
+            }
+        });
+        ThreadedController {
+            requests,
+            responses,
+            deadline,
+            move_deadline: deadline,
+            next_id: 0,
+            outstanding: None,
+            pondering: None,
+            last_score: None,
+            unknown_cells,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Sends `request` and waits for its response until `deadline`, skipping answers to
+    /// earlier requests that came in after theirs.
+    fn call(&mut self, request: impl FnOnce(u64) -> Request, deadline: Instant) -> Option<Response> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.requests.send(request(id)).ok()?;
+        loop {
+            match self.responses.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(response) if response.id != id => { continue }
+                Ok(response) => { return Some(response) }
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => { return None }
+            }
+        }
+    }
+
+    fn stop_pondering(&mut self) {
+        if let Some(token) = self.pondering.take() {
+            token.cancel();
+        }
+    }
+}
+
+impl PlayerController for ThreadedController {
+    fn get_action(&mut self, board: &Board, player_id: usize) -> Action {
+        self.stop_pondering();
+        let deadline = Instant::now() + self.move_deadline;
+        self.move_deadline = self.deadline;
+        let token = CancelToken::new();
+        self.outstanding = Some(token.clone());
+        let move_token = token.clone();
+        let response = self.call(|id| Request::Move { id, board: board.clone(), player_id, token: move_token }, deadline);
+        match response {
+            Some(Response { action: Some(action), score, .. }) => {
+                self.outstanding = None;
+                self.last_score = score;
+                action
+            }
+            _ => {
+                token.cancel();
+                self.last_score = None;
+                random_safe_action(board, player_id, &mut self.rng)
+            }
+        }
+    }
+
+    fn set_clock(&mut self, remaining: Duration, time_control: TimeControl) {
+        // Answer a little early so the hand-off isn't charged as an overrun.
+        self.move_deadline = self.deadline.min(remaining * 19 / 20);
+        let _ = self.requests.send(Request::Clock { remaining, time_control });
+    }
+
+    fn search_score(&self) -> Option<i32> {
+        self.last_score
+    }
+
+    fn unknown_cells(&self) -> UnknownCells {
+        self.unknown_cells
+    }
+
+    fn set_info_sink(&mut self, sink: Sender<SearchInfo>) {
+        let _ = self.requests.send(Request::InfoSink(sink));
+    }
+
+    fn can_ponder(&self) -> bool {
+        true
+    }
+
+    fn predict(&mut self, board: &Board, player_id: usize, opponent_id: usize) -> Option<Action> {
+        self.stop_pondering();
+        // The worker is still on a move it ran out of time for and couldn't answer anyway.
+        if self.outstanding.is_some() {
+            return None
+        }
+        let token = CancelToken::new();
+        self.pondering = Some(token.clone());
+        let deadline = Instant::now() + self.deadline;
+        let response = self.call(|id| Request::Predict { id, board: board.clone(), player_id, opponent_id, token }, deadline);
+        match response {
+            Some(Response { action: Some(action), .. }) => { Some(action) }
+            _ => {
+                self.stop_pondering();
+                None
+            }
+        }
+    }
+
+    fn ponder_hit(&mut self) {
+        self.stop_pondering();
+        let _ = self.requests.send(Request::PonderHit);
+    }
+
+    fn ponder_miss(&mut self) {
+        self.stop_pondering();
+        let _ = self.requests.send(Request::PonderMiss);
+    }
+}
+
+impl Drop for ThreadedController {
+    fn drop(&mut self) {
+        if let Some(token) = &self.outstanding {
+            token.cancel();
+        }
+        self.stop_pondering();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ignores its cancel token and answers long after any deadline.
+    struct StuckController;
+
+    impl PlayerController for StuckController {
+        fn get_action(&mut self, _board: &Board, _player_id: usize) -> Action {
+            thread::sleep(Duration::from_millis(500));
+            Action::Down
+        }
+
+        fn predict(&mut self, _board: &Board, _player_id: usize, _opponent_id: usize) -> Option<Action> {
+            Some(Action::Down)
+        }
+    }
+
+    #[test]
+    fn workers_stuck_on_a_move_are_not_asked_to_predict() {
+        let board = Board::new_default(4, 4).unwrap();
+        let mut controller = ThreadedController::new(Box::new(StuckController), Duration::from_millis(200));
+        controller.move_deadline = Duration::from_millis(10);
+        controller.get_action(&board, 0);
+        let start = Instant::now();
+        assert_eq!(controller.predict(&board, 0, 1), None);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,63 @@
+"""Smoke tests for the Python bindings. Build them first, e.g. with `maturin develop`."""
+import pytest
+
+import rust_tron
+
+
+def make_environment():
+    return rust_tron.Environment([None, rust_tron.ClockwiseController()])
+
+
+def test_reset_returns_one_observation_per_agent():
+    environment = make_environment()
+    assert environment.agent_ids == [0]
+    observations = environment.reset(0)
+    assert len(observations) == 1
+    planes, height, width = observations[0].shape
+    assert (height, width) == (10, 10)
+    assert len(observations[0].data) == planes * height * width
+
+
+def test_reset_is_deterministic_per_seed():
+    environment = make_environment()
+    first = environment.reset(7)[0].data
+    assert environment.reset(7)[0].data == first
+
+
+def test_step_runs_a_game_to_the_end():
+    environment = make_environment()
+    environment.reset(0)
+    done = False
+    for _ in range(500):
+        observations, rewards, done, info = environment.step([rust_tron.Action.Right])
+        assert len(observations) == len(rewards) == 1
+        assert info["turn"] >= 0
+        if done:
+            break
+    assert done
+    assert rewards[0] in (-1.0, 0.0, 1.0)
+    assert info["winner_id"] in (None, 0, 1)
+
+
+def test_step_needs_one_action_per_agent():
+    environment = make_environment()
+    environment.reset(0)
+    with pytest.raises(ValueError):
+        environment.step([])
+
+
+def test_python_controllers_play_against_rust_ones():
+    class Straight:
+        def get_action(self, board, player_id):
+            return board.legal_actions(player_id)[0]
+
+    environment = rust_tron.Environment([None, Straight()])
+    environment.reset(1)
+    _, _, done, _ = environment.step([rust_tron.Action.Down])
+    assert isinstance(done, bool)
+
+
+def test_encode_rejects_unknown_players():
+    board = rust_tron.generate_map(0)
+    with pytest.raises(ValueError):
+        board.encode(len(board.players()))